@@ -0,0 +1,7 @@
+//! Helpers shared by the benches, which can't reach the `#[cfg(test)]` ones of the library
+#![allow(dead_code)]
+
+#[path = "../src/test_util.rs"]
+mod test_util;
+
+pub use test_util::Lcg;
@@ -0,0 +1,52 @@
+//! Steps of the day 18 automaton on [`Grid`] against [`BitGrid`]
+//!
+//! Run with `cargo bench --bench day18`.
+use adventofcode_rust_2015::day18::{Automaton, BitGrid, Boundary, Grid, Rule};
+use common::Lcg;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+mod common;
+
+/// Grid with about a third of the lights on, from a linear congruential generator
+fn random_grid(size: usize) -> Grid {
+    let mut random = Lcg::new(18);
+    let mut grid = Grid::new(size, size);
+    for y in 0..size as i64 {
+        for x in 0..size as i64 {
+            grid.set(x, y, random.next_bits() < (1 << 31) / 3);
+        }
+    }
+    grid
+}
+
+fn step(c: &mut Criterion) {
+    let automaton = Automaton::new(Rule::LIFE, Boundary::Dead).unwrap();
+    let mut group = c.benchmark_group("day18 step");
+    group.sample_size(10);
+    for size in [100, 10_000] {
+        let grid = random_grid(size);
+        let packed = BitGrid::from(&grid);
+        let name = format!("{size}x{size}");
+        group.bench_with_input(BenchmarkId::new("Grid", &name), &grid, |b, grid| {
+            b.iter(|| automaton.step(grid))
+        });
+        group.bench_with_input(BenchmarkId::new("BitGrid", &name), &packed, |b, packed| {
+            b.iter(|| automaton.step_packed(packed))
+        });
+    }
+    group.finish();
+}
+
+/// Part 1 and 2 run 100 steps, where [`Automaton::run_packed`] reuses its grids
+fn run(c: &mut Criterion) {
+    let automaton = Automaton::new(Rule::LIFE, Boundary::Dead).unwrap();
+    let grid = random_grid(100);
+    let packed = BitGrid::from(&grid);
+    let mut group = c.benchmark_group("day18 100 steps on 100x100");
+    group.bench_function("Grid", |b| b.iter(|| automaton.run(&grid, 100)));
+    group.bench_function("BitGrid", |b| b.iter(|| automaton.run_packed(&packed, 100)));
+    group.finish();
+}
+
+criterion_group!(benches, step, run);
+criterion_main!(benches);
@@ -0,0 +1,156 @@
+//! Day 22 [`cheapest_win`] against the original depth-first `sim`
+//!
+//! Run with `cargo bench --bench day22`.
+use adventofcode_rust_2015::day22::{cheapest_win, parse_spellbook, Difficulty, SPELLBOOK};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::sync::{Arc, Mutex};
+
+type Spell = (i64, i64, i64, u64, u64, i64, u64);
+
+const MISSILE: Spell = (53, 4, 0, 0, 0, 0, 0);
+const DRAIN: Spell = (73, 2, 2, 0, 0, 0, 1);
+const SHIELD: Spell = (113, 0, 0, 7, 0, 6, 2);
+const POISON: Spell = (173, 3, 0, 0, 0, 6, 3);
+const RECHARGE: Spell = (229, 0, 0, 0, 101, 5, 4);
+const SPELLS: [Spell; 5] = [MISSILE, DRAIN, SHIELD, POISON, RECHARGE];
+
+/// The solver before [`cheapest_win`], unchanged
+fn least_mana(boss: (u64, u64), part_two: bool) -> u64 {
+    let least_mana = Arc::new(Mutex::new(u64::MAX));
+    sim(
+        least_mana.clone(),
+        boss.0 as i64,
+        boss.1,
+        50,
+        500,
+        vec![],
+        true,
+        0,
+        part_two,
+    );
+    let least_mana = least_mana.lock().unwrap();
+    *least_mana
+}
+
+#[allow(clippy::too_many_arguments)]
+fn sim(
+    least_mana_used: Arc<Mutex<u64>>,
+    mut boss_hitpoints: i64,
+    boss_damage: u64,
+    mut player_hitpoints: i64,
+    mut player_mana: i64,
+    active_spells: Vec<Spell>,
+    player_turn: bool,
+    mana_used: u64,
+    part_two: bool,
+) -> bool {
+    let mut player_armor = 0;
+    if part_two && player_turn {
+        player_hitpoints -= 1;
+        if player_hitpoints <= 0 {
+            return false;
+        }
+    }
+    let mut new_active_spells: Vec<Spell> = vec![];
+    for active_spell in active_spells {
+        if active_spell.5 >= 0 {
+            boss_hitpoints -= active_spell.1;
+            player_hitpoints += active_spell.2;
+            player_armor += active_spell.3;
+            player_mana += active_spell.4 as i64;
+        }
+        let new_active_spell: Spell = (
+            active_spell.0,
+            active_spell.1,
+            active_spell.2,
+            active_spell.3,
+            active_spell.4,
+            active_spell.5 - 1,
+            active_spell.6,
+        );
+        if new_active_spell.5 > 0 {
+            new_active_spells.push(new_active_spell);
+        }
+    }
+    {
+        let mut least_mana_used = least_mana_used.lock().unwrap();
+        if boss_hitpoints <= 0 {
+            if mana_used < *least_mana_used {
+                *least_mana_used = mana_used;
+            }
+            return true;
+        }
+        if mana_used >= *least_mana_used {
+            return false;
+        }
+        drop(least_mana_used);
+    }
+    if player_turn {
+        for spell in SPELLS {
+            let mut spell_already_active = false;
+            for new_active_spell in &new_active_spells {
+                if new_active_spell.6 == spell.6 {
+                    spell_already_active = true;
+                    break;
+                }
+            }
+            let spell_mana_cost = spell.0;
+            if spell_mana_cost <= player_mana && !spell_already_active {
+                let mut a: Vec<Spell> = new_active_spells.clone();
+                a.push(spell);
+                sim(
+                    least_mana_used.clone(),
+                    boss_hitpoints,
+                    boss_damage,
+                    player_hitpoints,
+                    player_mana - spell_mana_cost,
+                    a,
+                    false,
+                    mana_used + spell_mana_cost as u64,
+                    part_two,
+                );
+            }
+        }
+    } else {
+        let damage = (boss_damage - player_armor).max(1) as i64;
+        player_hitpoints -= damage;
+        if player_hitpoints > 0 {
+            sim(
+                least_mana_used,
+                boss_hitpoints,
+                boss_damage,
+                player_hitpoints,
+                player_mana,
+                new_active_spells,
+                true,
+                mana_used,
+                part_two,
+            );
+        }
+    }
+    true
+}
+
+fn solvers(c: &mut Criterion) {
+    let spellbook = parse_spellbook(SPELLBOOK).unwrap();
+    for (part, difficulty) in [(1, Difficulty::NORMAL), (2, Difficulty::HARD)] {
+        let mut group = c.benchmark_group(format!("day22 part{part}"));
+        group.sample_size(10);
+        // the puzzle input, then bosses with more hit points
+        for boss in [(58, 9), (71, 9), (80, 9)] {
+            let name = format!("{}hp {}dmg", boss.0, boss.1);
+            let mana = cheapest_win(&spellbook, &boss, &difficulty).map(|(mana, _)| mana);
+            assert_eq!(mana, Some(least_mana(boss, part == 2)));
+            group.bench_with_input(BenchmarkId::new("sim", &name), &boss, |b, boss| {
+                b.iter(|| least_mana(*boss, part == 2))
+            });
+            group.bench_with_input(BenchmarkId::new("cheapest_win", &name), &boss, |b, boss| {
+                b.iter(|| cheapest_win(&spellbook, boss, &difficulty))
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, solvers);
+criterion_main!(benches);
@@ -0,0 +1,58 @@
+//! Day 6 programs on the dense [`Grid`] against the [`SparseGrid`]
+//!
+//! Run with `cargo bench --bench day6`.
+use adventofcode_rust_2015::day6::{count, Grid, SparseGrid};
+use common::Lcg;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+mod common;
+
+/// Program of `length` random instructions on a `size` by `size` grid, from a linear
+/// congruential generator
+fn program(length: usize, size: u64) -> String {
+    let mut lcg = Lcg::new(6);
+    let mut random = |below: u64| lcg.below(below);
+    let mut program = String::new();
+    for _ in 0..length {
+        let action = ["turn on", "turn off", "toggle"][random(3) as usize];
+        let (x1, y1) = (random(size), random(size));
+        let (x2, y2) = (x1 + random(size - x1), y1 + random(size - y1));
+        program += &format!("{} {},{} through {},{}\n", action, x1, y1, x2, y2);
+    }
+    program
+}
+
+fn puzzle_size(c: &mut Criterion) {
+    let mut group = c.benchmark_group("day6 1000x1000");
+    group.sample_size(10);
+    for length in [300, 3000] {
+        let program = program(length, 1000);
+        group.bench_with_input(BenchmarkId::new("Grid", length), &program, |b, program| {
+            b.iter(|| count(program, Grid::<u32>::new(1000, 1000)))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("SparseGrid", length),
+            &program,
+            |b, program| b.iter(|| count(program, SparseGrid::<u32>::new())),
+        );
+    }
+    group.finish();
+}
+
+/// Far too large for a dense grid
+fn huge(c: &mut Criterion) {
+    let mut group = c.benchmark_group("day6 2^31x2^31");
+    group.sample_size(10);
+    for length in [1000, 5000, 20000] {
+        let program = program(length, 1 << 31);
+        group.bench_with_input(
+            BenchmarkId::new("SparseGrid", length),
+            &program,
+            |b, program| b.iter(|| count(program, SparseGrid::<u32>::new())),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, puzzle_size, huge);
+criterion_main!(benches);
@@ -65,9 +65,10 @@
 use itertools::Itertools;
 use regex::Regex;
 use std::collections::HashMap;
+use std::fmt;
 
 #[aoc_generator(day13)]
-fn parse_input(input: &str) -> anyhow::Result<TableRuleset> {
+pub fn parse_input(input: &str) -> anyhow::Result<TableRuleset> {
     let mut names: Vec<String> = Vec::new();
     let mut rules: Rules = HashMap::new();
     // Alice would gain 54 happiness units by sitting next to Bob.
@@ -118,25 +119,360 @@ fn part2(input: &TableRuleset) -> i64 {
 
 type Rules = HashMap<String, HashMap<String, i64>>;
 
-struct TableRuleset {
+/// The guests, in the order they first appear, and how each one feels about sitting next to
+/// each other one
+pub struct TableRuleset {
     rules: Rules,
     names: Vec<String>,
 }
 
 impl TableRuleset {
-    fn best_happiness(&self) -> i64 {
-        let mut best = i64::MIN;
-        for perm in self.names.iter().permutations(self.names.len()) {
-            let happiness = calc_happiness(&perm, &self.rules);
-            if happiness > best {
-                best = happiness;
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Total change in happiness of the best seating, `0` without guests
+    pub fn best_happiness(&self) -> i64 {
+        self.best_seating().map_or(0, |seating| seating.happiness)
+    }
+
+    /// Best seating around a round table. Since turning or mirroring the table doesn't change
+    /// who sits next to whom, the first guest is kept in the first seat and the second seat goes
+    /// to a guest who comes before the one in the last seat, which leaves `(n - 1)! / 2` of the
+    /// `n!` orders to try.
+    pub fn best_seating(&self) -> Option<Seating> {
+        let n = self.names.len();
+        let deltas = self.deltas();
+        let mut best: Option<(i64, Vec<usize>)> = None;
+        let mut consider = |order: Vec<usize>| {
+            let happiness = happiness(&deltas, &order);
+            if best.as_ref().is_none_or(|(b, _)| happiness > *b) {
+                best = Some((happiness, order));
+            }
+        };
+        if n == 0 {
+            return None;
+        } else if n <= 3 {
+            // every order is the same table
+            consider((0..n).collect());
+        } else {
+            for (second, last) in (1..n).tuple_combinations() {
+                let middle = (1..n).filter(|&guest| guest != second && guest != last);
+                for middle in middle.permutations(n - 3) {
+                    let mut order = vec![0, second];
+                    order.extend(middle);
+                    order.push(last);
+                    consider(order);
+                }
+            }
+        }
+        best.map(|(_, order)| self.seating(&order))
+    }
+
+    /// Happiness of each guest about each other guest, by index into `names`
+    fn deltas(&self) -> Vec<Vec<i64>> {
+        self.names
+            .iter()
+            .map(|left| {
+                self.names
+                    .iter()
+                    .map(|right| get_delta(&self.rules, left, right))
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn seating(&self, order: &[usize]) -> Seating {
+        let deltas = self.deltas();
+        let n = order.len();
+        let seats = (0..n)
+            .map(|seat| {
+                let (guest, left, right) = (
+                    order[seat],
+                    order[(seat + n - 1) % n],
+                    order[(seat + 1) % n],
+                );
+                Seat {
+                    guest: self.names[guest].clone(),
+                    left: self.names[left].clone(),
+                    from_left: deltas[guest][left],
+                    right: self.names[right].clone(),
+                    from_right: deltas[guest][right],
+                }
+            })
+            .collect();
+        Seating {
+            seats,
+            happiness: happiness(&deltas, order),
+        }
+    }
+}
+
+/// A guest at a round table, with how much happier they are for sitting next to each neighbour
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Seat {
+    pub guest: String,
+    pub left: String,
+    pub from_left: i64,
+    pub right: String,
+    pub from_right: i64,
+}
+
+/// The guests in the order they sit around the table, and their total change in happiness
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Seating {
+    pub seats: Vec<Seat>,
+    pub happiness: i64,
+}
+
+impl fmt::Display for Seating {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for seat in &self.seats {
+            writeln!(
+                f,
+                "{}: {:+} next to {}, {:+} next to {}",
+                seat.guest, seat.from_left, seat.left, seat.from_right, seat.right
+            )?;
+        }
+        write!(f, "total change in happiness: {}", self.happiness)
+    }
+}
+
+/// Hard rules for a [`Plan`]. Tables and seats are counted from `0`, and the seats of a table go
+/// around it, so that the last one is next to the first one.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct Constraints {
+    pinned: Vec<(String, usize, usize)>,
+    apart: Vec<(String, String)>,
+    together: Vec<(String, String)>,
+}
+
+impl Constraints {
+    pub fn new() -> Self {
+        Constraints::default()
+    }
+
+    /// `guest` must sit in seat `seat` of table `table`
+    pub fn pin(mut self, guest: &str, table: usize, seat: usize) -> Self {
+        self.pinned.push((guest.to_string(), table, seat));
+        self
+    }
+
+    /// `a` and `b` must not sit next to each other
+    pub fn apart(mut self, a: &str, b: &str) -> Self {
+        self.apart.push((a.to_string(), b.to_string()));
+        self
+    }
+
+    /// `a` and `b` must sit next to each other
+    pub fn together(mut self, a: &str, b: &str) -> Self {
+        self.together.push((a.to_string(), b.to_string()));
+        self
+    }
+}
+
+/// Seating of all guests at several round tables
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Plan {
+    pub tables: Vec<Seating>,
+    pub happiness: i64,
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, table) in self.tables.iter().enumerate() {
+            writeln!(f, "table {}:", idx)?;
+            writeln!(f, "{}", table)?;
+        }
+        write!(f, "total change in happiness: {}", self.happiness)
+    }
+}
+
+impl TableRuleset {
+    /// Best seating of the guests at round tables with the given numbers of seats, which have to
+    /// add up to the number of guests, under `constraints`.
+    ///
+    /// Seats are filled one after the other, backtracking as soon as a constraint is broken. At a
+    /// table without pinned guests only the guest who comes first in `names` takes the first
+    /// seat and the second seat goes to a guest who comes before the one in the last seat, like
+    /// in [`TableRuleset::best_seating`], and of two such tables of the same size the first one
+    /// gets the guest who comes first.
+    pub fn plan(&self, tables: &[usize], constraints: &Constraints) -> anyhow::Result<Plan> {
+        let guests = self.names.len();
+        let seats: usize = tables.iter().sum();
+        if seats != guests {
+            bail!("{} seats for {} guests", seats, guests);
+        }
+        if let Some(table) = tables.iter().position(|&size| size == 0) {
+            bail!("table {} has no seats", table);
+        }
+        let index = |guest: &str| {
+            self.names
+                .iter()
+                .position(|name| name == guest)
+                .ok_or_else(|| anyhow!("unknown guest '{}'", guest))
+        };
+        let pairs = |pairs: &[(String, String)]| -> anyhow::Result<Vec<(usize, usize)>> {
+            pairs
+                .iter()
+                .map(|(a, b)| Ok((index(a)?, index(b)?)))
+                .collect()
+        };
+        let mut search = Search {
+            deltas: self.deltas(),
+            tables: vec![],
+            seats: vec![None; seats],
+            placed: vec![None; guests],
+            pinned: vec![false; guests],
+            apart: pairs(&constraints.apart)?,
+            together: pairs(&constraints.together)?,
+            best: None,
+        };
+        for (table, &size) in tables.iter().enumerate() {
+            for _ in 0..size {
+                search.tables.push((table, size));
+            }
+        }
+        for (guest, table, seat) in &constraints.pinned {
+            let guest = index(guest)?;
+            if *table >= tables.len() || *seat >= tables[*table] {
+                bail!("table {} has no seat {}", table, seat);
+            }
+            let position = tables[..*table].iter().sum::<usize>() + seat;
+            if search.seats[position].is_some_and(|other| other != guest)
+                || search.placed[guest].is_some_and(|other| other != position)
+            {
+                bail!(
+                    "'{}' and another pin share a seat or guest",
+                    self.names[guest]
+                );
+            }
+            search.seats[position] = Some(guest);
+            search.placed[guest] = Some(position);
+            search.pinned[guest] = true;
+        }
+        if (0..guests).all(|guest| !search.pinned[guest] || search.allowed(guest)) {
+            search.fill(0);
+        }
+        let (happiness, order) = search
+            .best
+            .ok_or_else(|| anyhow!("no seating satisfies the constraints"))?;
+        let mut order = order.as_slice();
+        let tables = tables
+            .iter()
+            .map(|&size| {
+                let (table, rest) = order.split_at(size);
+                order = rest;
+                self.seating(table)
+            })
+            .collect();
+        Ok(Plan { tables, happiness })
+    }
+}
+
+/// Backtracking state of [`TableRuleset::plan`], where a position is the index of a seat across
+/// all tables
+struct Search {
+    deltas: Vec<Vec<i64>>,
+    /// table and its size at each position
+    tables: Vec<(usize, usize)>,
+    /// guest at each position
+    seats: Vec<Option<usize>>,
+    /// position of each guest
+    placed: Vec<Option<usize>>,
+    pinned: Vec<bool>,
+    apart: Vec<(usize, usize)>,
+    together: Vec<(usize, usize)>,
+    best: Option<(i64, Vec<usize>)>,
+}
+
+impl Search {
+    fn adjacent(&self, a: usize, b: usize) -> bool {
+        let (table, size) = self.tables[a];
+        let distance = a.abs_diff(b);
+        a != b && self.tables[b].0 == table && (distance == 1 || distance == size - 1)
+    }
+
+    /// Whether the constraints between `guest` and the guests placed so far hold
+    fn allowed(&self, guest: usize) -> bool {
+        let position = self.placed[guest].unwrap();
+        let partner = |(a, b): &(usize, usize)| match guest {
+            _ if guest == *a => self.placed[*b],
+            _ if guest == *b => self.placed[*a],
+            _ => None,
+        };
+        self.apart
+            .iter()
+            .filter_map(partner)
+            .all(|other| !self.adjacent(position, other))
+            && self
+                .together
+                .iter()
+                .filter_map(partner)
+                .all(|other| self.adjacent(position, other))
+    }
+
+    /// Whether `guest` may take `position` at a table without pinned guests, so that turned,
+    /// mirrored or swapped tables are only tried once
+    fn canonical(&self, position: usize, guest: usize) -> bool {
+        let (table, size) = self.tables[position];
+        let first = position
+            - self.tables[..position]
+                .iter()
+                .rev()
+                .take_while(|(t, _)| *t == table)
+                .count();
+        if (first..first + size).any(|p| self.seats[p].is_some_and(|g| self.pinned[g])) {
+            return true;
+        }
+        if position == first {
+            // the table before, if it is the same size and has no pinned guests either
+            let previous = first.checked_sub(size).filter(|&previous| {
+                self.tables[previous] == (table - 1, size)
+                    && (previous..first).all(|p| !self.pinned[self.seats[p].unwrap()])
+            });
+            previous.is_none_or(|previous| self.seats[previous].unwrap() < guest)
+        } else if position == first + size - 1 && size >= 3 {
+            guest > self.seats[first].unwrap() && guest > self.seats[first + 1].unwrap()
+        } else {
+            guest > self.seats[first].unwrap()
+        }
+    }
+
+    fn fill(&mut self, position: usize) {
+        if position == self.seats.len() {
+            let order: Vec<usize> = self.seats.iter().map(|guest| guest.unwrap()).collect();
+            let mut happiness = 0;
+            let mut start = 0;
+            while start < order.len() {
+                let size = self.tables[start].1;
+                happiness += self::happiness(&self.deltas, &order[start..start + size]);
+                start += size;
             }
+            if self.best.as_ref().is_none_or(|(best, _)| happiness > *best) {
+                self.best = Some((happiness, order));
+            }
+            return;
+        }
+        if self.seats[position].is_some() {
+            return self.fill(position + 1);
+        }
+        for guest in 0..self.placed.len() {
+            if self.placed[guest].is_some() || !self.canonical(position, guest) {
+                continue;
+            }
+            self.seats[position] = Some(guest);
+            self.placed[guest] = Some(position);
+            if self.allowed(guest) {
+                self.fill(position + 1);
+            }
+            self.seats[position] = None;
+            self.placed[guest] = None;
         }
-        best
     }
 }
 
-fn add_yourself(rules: &TableRuleset) -> TableRuleset {
+pub fn add_yourself(rules: &TableRuleset) -> TableRuleset {
     let mut names = rules.names.clone();
     let mut rules = rules.rules.clone();
     names.push("me".into());
@@ -146,27 +482,28 @@ fn add_yourself(rules: &TableRuleset) -> TableRuleset {
 }
 
 fn get_delta(rules: &Rules, left: &str, right: &str) -> i64 {
-    *rules.get(left).unwrap().get(right).unwrap_or(&0)
+    rules
+        .get(left)
+        .and_then(|deltas| deltas.get(right))
+        .copied()
+        .unwrap_or(0)
 }
 
-fn calc_happiness(seating: &Vec<&String>, rules: &Rules) -> i64 {
-    let mut by_name: HashMap<String, i64> = HashMap::new();
-    for name in seating {
-        by_name.insert((*name).clone(), 0);
-    }
-    for pair in seating.windows(2) {
-        *by_name.get_mut(pair[0]).unwrap() += get_delta(rules, pair[0], pair[1]);
-        *by_name.get_mut(pair[1]).unwrap() += get_delta(rules, pair[1], pair[0]);
-    }
-    let last_idx = seating.len() - 1;
-    *by_name.get_mut(seating[0]).unwrap() += get_delta(rules, seating[0], seating[last_idx]);
-    *by_name.get_mut(seating[last_idx]).unwrap() += get_delta(rules, seating[last_idx], seating[0]);
-    by_name.values().sum()
+/// Total change in happiness of the guests around a round table in `order`
+fn happiness(deltas: &[Vec<i64>], order: &[usize]) -> i64 {
+    let n = order.len();
+    (0..n)
+        .map(|seat| {
+            let (guest, right) = (order[seat], order[(seat + 1) % n]);
+            deltas[guest][right] + deltas[right][guest]
+        })
+        .sum()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_util::Lcg;
 
     const EXAMPLE: &str = "Alice would gain 54 happiness units by sitting next to Bob.
 Alice would lose 79 happiness units by sitting next to Carol.
@@ -186,4 +523,229 @@ David would gain 41 happiness units by sitting next to Carol.";
         let rules = parse_input(EXAMPLE).expect("failed to parse");
         assert_eq!(rules.best_happiness(), 330);
     }
+
+    #[test]
+    fn best_seating() {
+        let rules = parse_input(EXAMPLE).expect("failed to parse");
+        let seating = rules.best_seating().unwrap();
+        assert_eq!(
+            seating.to_string(),
+            "Alice: -2 next to David, +54 next to Bob
+Bob: +83 next to Alice, -7 next to Carol
+Carol: +60 next to Bob, +55 next to David
+David: +41 next to Carol, +46 next to Alice
+total change in happiness: 330"
+        );
+        let yourself = add_yourself(&rules).best_seating().unwrap();
+        assert_eq!(yourself.seats.len(), 5);
+        assert_eq!(
+            yourself.happiness,
+            yourself
+                .seats
+                .iter()
+                .map(|seat| seat.from_left + seat.from_right)
+                .sum::<i64>()
+        );
+        assert_eq!(yourself.happiness, brute_force(&add_yourself(&rules)));
+    }
+
+    /// Best happiness over every order of the guests
+    fn brute_force(rules: &TableRuleset) -> i64 {
+        let deltas = rules.deltas();
+        (0..rules.names.len())
+            .permutations(rules.names.len())
+            .map(|order| happiness(&deltas, &order))
+            .max()
+            .unwrap()
+    }
+
+    #[test]
+    fn symmetry_reduction_agrees_with_brute_force() {
+        let mut random = Lcg::new(13);
+        for n in 1..=7 {
+            let mut input = vec![];
+            for (a, b) in (0..n).cartesian_product(0..n).filter(|(a, b)| a != b) {
+                let delta = random.below(200);
+                let (verb, delta) = if delta < 100 {
+                    ("gain", delta)
+                } else {
+                    ("lose", delta - 100)
+                };
+                input.push(format!(
+                    "Guest{} would {} {} happiness units by sitting next to Guest{}.",
+                    a, verb, delta, b
+                ));
+            }
+            let rules = parse_input(&input.join("\n")).unwrap();
+            if n == 1 {
+                assert!(rules.best_seating().is_none());
+                continue;
+            }
+            assert_eq!(rules.best_happiness(), brute_force(&rules));
+        }
+    }
+
+    /// Best happiness over every order of the guests at `tables` which satisfies `constraints`
+    fn brute_force_plan(
+        rules: &TableRuleset,
+        tables: &[usize],
+        constraints: &Constraints,
+    ) -> Option<i64> {
+        let deltas = rules.deltas();
+        let index = |guest: &String| rules.names.iter().position(|name| name == guest).unwrap();
+        let mut starts = vec![0];
+        for size in tables {
+            starts.push(starts.last().unwrap() + size);
+        }
+        (0..rules.names.len())
+            .permutations(rules.names.len())
+            .filter(|order| {
+                let position = |guest| order.iter().position(|g| *g == guest).unwrap();
+                let adjacent = |a: &String, b: &String| {
+                    let (a, b) = (position(index(a)), position(index(b)));
+                    starts.windows(2).any(|table| {
+                        let size = table[1] - table[0];
+                        let range = table[0]..table[1];
+                        range.contains(&a)
+                            && range.contains(&b)
+                            && a != b
+                            && (a.abs_diff(b) == 1 || a.abs_diff(b) == size - 1)
+                    })
+                };
+                constraints
+                    .pinned
+                    .iter()
+                    .all(|(guest, table, seat)| position(index(guest)) == starts[*table] + seat)
+                    && constraints.apart.iter().all(|(a, b)| !adjacent(a, b))
+                    && constraints.together.iter().all(|(a, b)| adjacent(a, b))
+            })
+            .map(|order| {
+                starts
+                    .windows(2)
+                    .map(|table| happiness(&deltas, &order[table[0]..table[1]]))
+                    .sum()
+            })
+            .max()
+    }
+
+    #[test]
+    fn plan_with_constraints() {
+        let rules = parse_input(EXAMPLE).expect("failed to parse");
+        let plan = rules.plan(&[4], &Constraints::new()).unwrap();
+        assert_eq!(plan.happiness, 330);
+        assert_eq!(plan.tables[0], rules.best_seating().unwrap());
+
+        let plan = rules
+            .plan(&[4], &Constraints::new().pin("Carol", 0, 2))
+            .unwrap();
+        assert_eq!(plan.happiness, 330);
+        assert_eq!(plan.tables[0].seats[2].guest, "Carol");
+
+        let constraints = Constraints::new().apart("Alice", "Bob");
+        let plan = rules.plan(&[4], &constraints).unwrap();
+        assert_eq!(
+            Some(plan.happiness),
+            brute_force_plan(&rules, &[4], &constraints)
+        );
+        assert!(plan.tables[0]
+            .seats
+            .iter()
+            .all(|seat| seat.guest != "Alice" || (seat.left != "Bob" && seat.right != "Bob")));
+
+        let constraints = Constraints::new().together("Alice", "Carol");
+        let plan = rules.plan(&[2, 2], &constraints).unwrap();
+        assert_eq!(
+            plan.to_string(),
+            "table 0:
+Alice: -79 next to Carol, -79 next to Carol
+Carol: -62 next to Alice, -62 next to Alice
+total change in happiness: -282
+table 1:
+Bob: -63 next to David, -63 next to David
+David: -7 next to Bob, -7 next to Bob
+total change in happiness: -140
+total change in happiness: -422"
+        );
+    }
+
+    #[test]
+    fn plan_errors() {
+        let rules = parse_input(EXAMPLE).expect("failed to parse");
+        let error = |tables: &[usize], constraints: Constraints| {
+            rules.plan(tables, &constraints).unwrap_err().to_string()
+        };
+        assert_eq!(error(&[3], Constraints::new()), "3 seats for 4 guests");
+        assert_eq!(error(&[4, 0], Constraints::new()), "table 1 has no seats");
+        assert_eq!(
+            error(&[4], Constraints::new().apart("Alice", "Eve")),
+            "unknown guest 'Eve'"
+        );
+        assert_eq!(
+            error(&[2, 2], Constraints::new().pin("Bob", 1, 2)),
+            "table 1 has no seat 2"
+        );
+        assert_eq!(
+            error(&[4], Constraints::new().pin("Bob", 0, 1).pin("Carol", 0, 1)),
+            "'Carol' and another pin share a seat or guest"
+        );
+        assert_eq!(
+            error(
+                &[4],
+                Constraints::new()
+                    .together("Alice", "Bob")
+                    .together("Alice", "Carol")
+                    .together("Alice", "David")
+            ),
+            "no seating satisfies the constraints"
+        );
+        assert_eq!(
+            error(
+                &[2, 2],
+                Constraints::new()
+                    .pin("Alice", 0, 0)
+                    .pin("Bob", 1, 0)
+                    .together("Alice", "Bob")
+            ),
+            "no seating satisfies the constraints"
+        );
+    }
+
+    #[test]
+    fn plan_agrees_with_brute_force() {
+        let rules = add_yourself(&parse_input(EXAMPLE).unwrap());
+        let guests = ["Alice", "Bob", "Carol", "David", "me"];
+        let mut lcg = Lcg::new(25);
+        let mut random = |below: usize| lcg.below(below as u64) as usize;
+        for tables in [
+            vec![5],
+            vec![2, 3],
+            vec![3, 2],
+            vec![1, 2, 2],
+            vec![1, 1, 3],
+        ] {
+            for _ in 0..20 {
+                let mut constraints = Constraints::new();
+                for _ in 0..random(3) {
+                    let (a, b) = (guests[random(5)], guests[random(5)]);
+                    constraints = match random(3) {
+                        0 => constraints.apart(a, b),
+                        1 => constraints.together(a, b),
+                        _ => {
+                            let table = random(tables.len());
+                            constraints.pin(a, table, random(tables[table]))
+                        }
+                    };
+                }
+                let expected = brute_force_plan(&rules, &tables, &constraints);
+                match rules.plan(&tables, &constraints) {
+                    Ok(plan) => {
+                        assert_eq!(Some(plan.happiness), expected, "{:?}", constraints);
+                        let sum: i64 = plan.tables.iter().map(|table| table.happiness).sum();
+                        assert_eq!(plan.happiness, sum);
+                    }
+                    Err(_) => assert_eq!(expected, None, "{:?}", constraints),
+                }
+            }
+        }
+    }
 }
@@ -154,111 +154,1316 @@
 //! In your grid of 100x100 lights, given your initial configuration, but with the four
 //! corners always in the on state, how many lights are on after 100 steps?
 
-#[aoc_generator(day18)]
-fn parse_input(input: &str) -> Vec<Vec<bool>> {
-    input
-        .lines()
-        .map(|line| line.chars().map(|c| c == '#').collect())
-        .collect()
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Lights on a rectangle of the plane, `origin` being the coordinates of the top left light
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Grid {
+    origin: (i64, i64),
+    width: usize,
+    height: usize,
+    cells: Vec<bool>,
 }
 
-/// Part 1: In your grid of 100x100 lights, given your initial configuration,
-/// how many lights are on after 100 steps?
-#[aoc(day18, part1)]
-fn part1(input: &[Vec<bool>]) -> usize {
-    let grid = evolve1(input, 100);
-    count_on(&grid)
-}
-
-fn evolve1(input: &[Vec<bool>], steps: usize) -> Vec<Vec<bool>> {
-    let mut next = Vec::from(input);
-    for _ in 0..steps {
-        next = execute_step(&next);
-    }
-    next
-}
-
-fn execute_step(grid: &[Vec<bool>]) -> Vec<Vec<bool>> {
-    let width = grid[0].len();
-    let height = grid.len();
-    let mut next = create_grid(width, height);
-    for y in 0..height {
-        for x in 0..width {
-            let mut alive_neighbors = 0;
-            for dy in -1isize..=1 {
-                for dx in -1isize..=1 {
-                    let y = y as isize + dy;
-                    let x = x as isize + dx;
-                    if (dx == 0 && dy == 0)
-                        || y < 0
-                        || y >= height as isize
-                        || x < 0
-                        || x >= width as isize
-                    {
+impl Grid {
+    /// A grid with all lights off
+    pub fn new(width: usize, height: usize) -> Self {
+        Grid::with_origin((0, 0), width, height)
+    }
+
+    fn with_origin(origin: (i64, i64), width: usize, height: usize) -> Self {
+        Grid {
+            origin,
+            width,
+            height,
+            cells: vec![false; width * height],
+        }
+    }
+
+    pub fn origin(&self) -> (i64, i64) {
+        self.origin
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    fn index(&self, x: i64, y: i64) -> Option<usize> {
+        let (x, y) = (x - self.origin.0, y - self.origin.1);
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return None;
+        }
+        Some(y as usize * self.width + x as usize)
+    }
+
+    /// Whether the light at `(x, y)` is on, lights outside of the grid always being off
+    pub fn get(&self, x: i64, y: i64) -> bool {
+        self.index(x, y).is_some_and(|idx| self.cells[idx])
+    }
+
+    /// Turns the light at `(x, y)` on or off, panics if it is outside of the grid
+    pub fn set(&mut self, x: i64, y: i64, on: bool) {
+        let idx = self
+            .index(x, y)
+            .unwrap_or_else(|| panic!("({}, {}) is outside of the grid", x, y));
+        self.cells[idx] = on;
+    }
+
+    pub fn count_on(&self) -> usize {
+        self.cells.iter().filter(|cell| **cell).count()
+    }
+
+    /// Coordinates of all lights which are on, row by row
+    pub fn live_cells(&self) -> impl Iterator<Item = (i64, i64)> + '_ {
+        self.cells
+            .iter()
+            .enumerate()
+            .filter(|(_, cell)| **cell)
+            .map(|(idx, _)| {
+                (
+                    self.origin.0 + (idx % self.width) as i64,
+                    self.origin.1 + (idx / self.width) as i64,
+                )
+            })
+    }
+
+    /// Copy of the lights within another rectangle
+    fn resized(&self, origin: (i64, i64), width: usize, height: usize) -> Grid {
+        let mut grid = Grid::with_origin(origin, width, height);
+        for (x, y) in self.live_cells() {
+            if let Some(idx) = grid.index(x, y) {
+                grid.cells[idx] = true;
+            }
+        }
+        grid
+    }
+
+    /// Shrinks the grid to the smallest rectangle containing all lights which are on
+    fn crop(&self) -> Grid {
+        let (mut min, mut max) = ((i64::MAX, i64::MAX), (i64::MIN, i64::MIN));
+        for (x, y) in self.live_cells() {
+            min = (min.0.min(x), min.1.min(y));
+            max = (max.0.max(x), max.1.max(y));
+        }
+        if min.0 > max.0 {
+            return Grid::with_origin(self.origin, 0, 0);
+        }
+        let width = (max.0 - min.0 + 1) as usize;
+        let height = (max.1 - min.1 + 1) as usize;
+        self.resized(min, width, height)
+    }
+}
+
+/// A `#` means "on", and a `.` means "off".
+impl FromStr for Grid {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let rows: Vec<&str> = input.lines().collect();
+        let width = rows.first().map_or(0, |row| row.len());
+        let mut grid = Grid::new(width, rows.len());
+        for (y, row) in rows.iter().enumerate() {
+            if row.len() != width {
+                bail!(
+                    "line {}: expected {} lights but got {}",
+                    y + 1,
+                    width,
+                    row.len()
+                );
+            }
+            for (x, c) in row.chars().enumerate() {
+                match c {
+                    '#' => grid.set(x as i64, y as i64, true),
+                    '.' => {}
+                    _ => bail!("line {}: unexpected {:?}", y + 1, c),
+                }
+            }
+        }
+        Ok(grid)
+    }
+}
+
+impl fmt::Display for Grid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (y, row) in self.cells.chunks(self.width.max(1)).enumerate() {
+            if y > 0 {
+                writeln!(f)?;
+            }
+            for cell in row {
+                write!(f, "{}", if *cell { '#' } else { '.' })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Which numbers of neighbors that are on turn a light on (birth) or keep it on (survival),
+/// written like `B3/S23`
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Rule {
+    /// bit `n` is set if a light which is off turns on with `n` neighbors on
+    birth: u16,
+    /// bit `n` is set if a light which is on stays on with `n` neighbors on
+    survival: u16,
+}
+
+impl Rule {
+    /// - A light which is on stays on when `2` or `3` neighbors are on, and turns off otherwise.
+    /// - A light which is off turns on if exactly `3` neighbors are on, and stays off otherwise.
+    pub const LIFE: Rule = Rule {
+        birth: 1 << 3,
+        survival: 1 << 2 | 1 << 3,
+    };
+
+    /// Next state of a light given its current state and the number of neighbors that are on
+    pub fn next(&self, on: bool, neighbors: usize) -> bool {
+        let counts = if on { self.survival } else { self.birth };
+        counts & 1 << neighbors != 0
+    }
+}
+
+impl FromStr for Rule {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (birth, survival) = input
+            .split_once('/')
+            .ok_or_else(|| anyhow!("rule {:?} must look like B3/S23", input))?;
+        let counts = |part: &str, prefix: char| -> anyhow::Result<u16> {
+            let digits = part
+                .strip_prefix(prefix)
+                .or_else(|| part.strip_prefix(prefix.to_ascii_lowercase()))
+                .ok_or_else(|| anyhow!("rule {:?} must look like B3/S23", input))?;
+            digits.chars().try_fold(0, |counts, digit| match digit {
+                '0'..='8' => Ok(counts | 1 << (digit as u8 - b'0')),
+                _ => Err(anyhow!(
+                    "rule {:?}: {:?} is not a neighbor count",
+                    input,
+                    digit
+                )),
+            })
+        };
+        Ok(Rule {
+            birth: counts(birth, 'B')?,
+            survival: counts(survival, 'S')?,
+        })
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let digits = |counts: u16| -> String {
+            (0..=8)
+                .filter(|n| counts & 1 << n != 0)
+                .map(|n| char::from(b'0' + n as u8))
+                .collect()
+        };
+        write!(f, "B{}/S{}", digits(self.birth), digits(self.survival))
+    }
+}
+
+/// What lies beyond the edges of the grid
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Boundary {
+    /// Lights on the edge of the grid might have fewer than eight neighbors;
+    /// the missing ones always count as "off".
+    Dead,
+    /// The edges wrap around, so the grid is the surface of a torus
+    Toroidal,
+    /// The grid grows as needed to hold all lights which are on
+    Infinite,
+}
+
+/// A cellular automaton running on a [`Grid`]
+#[derive(PartialEq, Debug, Clone)]
+pub struct Automaton {
+    pub rule: Rule,
+    pub boundary: Boundary,
+    /// Lights which are stuck on and can't be turned off
+    pub pinned: HashSet<(i64, i64)>,
+}
+
+impl Automaton {
+    /// Fails for a rule which turns on lights without neighbors on an infinite grid
+    pub fn new(rule: Rule, boundary: Boundary) -> anyhow::Result<Self> {
+        if boundary == Boundary::Infinite && rule.next(false, 0) {
+            bail!("{} can't run on an infinite grid", rule);
+        }
+        Ok(Automaton {
+            rule,
+            boundary,
+            pinned: HashSet::new(),
+        })
+    }
+
+    /// Adds lights which are stuck on
+    pub fn pin(mut self, cells: impl IntoIterator<Item = (i64, i64)>) -> Self {
+        self.pinned.extend(cells);
+        self
+    }
+
+    /// Copy of `grid` with the pinned lights on, grown to hold them on an infinite grid
+    fn with_pins(&self, grid: &Grid) -> Grid {
+        let mut grid = grid.clone();
+        if self.boundary == Boundary::Infinite && !self.pinned.is_empty() {
+            let (mut min, mut max) = (grid.origin, grid.origin);
+            max = (
+                max.0 + grid.width as i64 - 1,
+                max.1 + grid.height as i64 - 1,
+            );
+            for (x, y) in &self.pinned {
+                min = (min.0.min(*x), min.1.min(*y));
+                max = (max.0.max(*x), max.1.max(*y));
+            }
+            let width = (max.0 - min.0 + 1) as usize;
+            let height = (max.1 - min.1 + 1) as usize;
+            grid = grid.resized(min, width, height);
+        }
+        for (x, y) in &self.pinned {
+            if let Some(idx) = grid.index(*x, *y) {
+                grid.cells[idx] = true;
+            }
+        }
+        grid
+    }
+
+    /// Whether the light at `(x, y)` is on, following the boundary outside of the grid
+    fn lookup(&self, grid: &Grid, x: i64, y: i64) -> bool {
+        match self.boundary {
+            Boundary::Toroidal => grid.get(
+                grid.origin.0 + (x - grid.origin.0).rem_euclid(grid.width as i64),
+                grid.origin.1 + (y - grid.origin.1).rem_euclid(grid.height as i64),
+            ),
+            _ => grid.get(x, y),
+        }
+    }
+
+    /// All of the lights update simultaneously; they all consider the same current state
+    /// before moving to the next.
+    pub fn step(&self, grid: &Grid) -> Grid {
+        let current = self.with_pins(grid);
+        let mut next = match self.boundary {
+            Boundary::Infinite => Grid::with_origin(
+                (current.origin.0 - 1, current.origin.1 - 1),
+                current.width + 2,
+                current.height + 2,
+            ),
+            _ => Grid::with_origin(current.origin, current.width, current.height),
+        };
+        for idx in 0..next.cells.len() {
+            let x = next.origin.0 + (idx % next.width) as i64;
+            let y = next.origin.1 + (idx / next.width) as i64;
+            let mut neighbors = 0;
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    if (dx != 0 || dy != 0) && self.lookup(&current, x + dx, y + dy) {
+                        neighbors += 1;
+                    }
+                }
+            }
+            next.cells[idx] = self.rule.next(current.get(x, y), neighbors);
+        }
+        let next = self.with_pins(&next);
+        match self.boundary {
+            Boundary::Infinite => next.crop(),
+            _ => next,
+        }
+    }
+
+    /// The grid after `steps` steps, with the pinned lights on
+    pub fn run(&self, grid: &Grid, steps: usize) -> Grid {
+        let mut grid = self.with_pins(grid);
+        for _ in 0..steps {
+            grid = self.step(&grid);
+        }
+        grid
+    }
+}
+
+/// A [`Grid`] packed into `u64` words, one bit per light, so that [`Automaton::step_packed`]
+/// updates 64 lights at once
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct BitGrid {
+    origin: (i64, i64),
+    width: usize,
+    height: usize,
+    /// words per row, bit `x % 64` of word `x / 64` holding the light in column `x`
+    stride: usize,
+    words: Vec<u64>,
+}
+
+impl BitGrid {
+    /// A grid with all lights off
+    pub fn new(width: usize, height: usize) -> Self {
+        BitGrid::with_origin((0, 0), width, height)
+    }
+
+    fn with_origin(origin: (i64, i64), width: usize, height: usize) -> Self {
+        let stride = width.div_ceil(64);
+        BitGrid {
+            origin,
+            width,
+            height,
+            stride,
+            words: vec![0; stride * height],
+        }
+    }
+
+    pub fn origin(&self) -> (i64, i64) {
+        self.origin
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Word index and bit mask of the light at `(x, y)`
+    fn index(&self, x: i64, y: i64) -> Option<(usize, u64)> {
+        let (x, y) = (x - self.origin.0, y - self.origin.1);
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return None;
+        }
+        let (x, y) = (x as usize, y as usize);
+        Some((y * self.stride + x / 64, 1 << (x % 64)))
+    }
+
+    /// Whether the light at `(x, y)` is on, lights outside of the grid always being off
+    pub fn get(&self, x: i64, y: i64) -> bool {
+        self.index(x, y)
+            .is_some_and(|(idx, bit)| self.words[idx] & bit != 0)
+    }
+
+    /// Turns the light at `(x, y)` on or off, panics if it is outside of the grid
+    pub fn set(&mut self, x: i64, y: i64, on: bool) {
+        let (idx, bit) = self
+            .index(x, y)
+            .unwrap_or_else(|| panic!("({}, {}) is outside of the grid", x, y));
+        if on {
+            self.words[idx] |= bit;
+        } else {
+            self.words[idx] &= !bit;
+        }
+    }
+
+    pub fn count_on(&self) -> usize {
+        self.words
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    /// Coordinates of all lights which are on, row by row
+    pub fn live_cells(&self) -> impl Iterator<Item = (i64, i64)> + '_ {
+        self.words.iter().enumerate().flat_map(move |(idx, word)| {
+            let y = self.origin.1 + (idx / self.stride) as i64;
+            let x = self.origin.0 + (idx % self.stride * 64) as i64;
+            let mut word = *word;
+            std::iter::from_fn(move || {
+                if word == 0 {
+                    return None;
+                }
+                let bit = word.trailing_zeros();
+                word &= word - 1;
+                Some((x + bit as i64, y))
+            })
+        })
+    }
+
+    /// Bits of the last word in a row which hold lights
+    fn last_word_mask(&self) -> u64 {
+        match self.width % 64 {
+            0 => !0,
+            bits => (1 << bits) - 1,
+        }
+    }
+}
+
+impl From<&Grid> for BitGrid {
+    fn from(grid: &Grid) -> Self {
+        let mut packed = BitGrid::with_origin(grid.origin, grid.width, grid.height);
+        for (x, y) in grid.live_cells() {
+            packed.set(x, y, true);
+        }
+        packed
+    }
+}
+
+impl From<&BitGrid> for Grid {
+    fn from(packed: &BitGrid) -> Self {
+        let mut grid = Grid::with_origin(packed.origin, packed.width, packed.height);
+        for (x, y) in packed.live_cells() {
+            grid.set(x, y, true);
+        }
+        grid
+    }
+}
+
+impl fmt::Display for BitGrid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Grid::from(self).fmt(f)
+    }
+}
+
+impl Automaton {
+    /// Same as [`Automaton::step`] on a packed grid
+    pub fn step_packed(&self, grid: &BitGrid) -> BitGrid {
+        if self.boundary == Boundary::Infinite {
+            // grow the grid to make room for new lights, step it as if it had dead edges and
+            // shrink it back around the lights which are on
+            let grid = Grid::from(grid);
+            let grid = self.with_pins(&grid);
+            let origin = (grid.origin.0 - 1, grid.origin.1 - 1);
+            let grid = grid.resized(origin, grid.width + 2, grid.height + 2);
+            let current = BitGrid::from(&grid);
+            let mut next = current.clone();
+            self.step_words(&current, &mut next);
+            let next = self.with_pins(&Grid::from(&next));
+            return BitGrid::from(&next.crop());
+        }
+        let mut current = grid.clone();
+        self.pin_packed(&mut current);
+        let mut next = current.clone();
+        self.step_words(&current, &mut next);
+        next
+    }
+
+    /// Same as [`Automaton::run`] on a packed grid, reusing the same two grids for all steps
+    /// unless the grid is infinite
+    pub fn run_packed(&self, grid: &BitGrid, steps: usize) -> BitGrid {
+        if self.boundary == Boundary::Infinite {
+            let mut grid = BitGrid::from(&self.with_pins(&Grid::from(grid)));
+            for _ in 0..steps {
+                grid = self.step_packed(&grid);
+            }
+            return grid;
+        }
+        let mut current = grid.clone();
+        self.pin_packed(&mut current);
+        let mut next = current.clone();
+        for _ in 0..steps {
+            self.step_words(&current, &mut next);
+            std::mem::swap(&mut current, &mut next);
+        }
+        current
+    }
+
+    fn pin_packed(&self, grid: &mut BitGrid) {
+        for (x, y) in &self.pinned {
+            if let Some((idx, bit)) = grid.index(*x, *y) {
+                grid.words[idx] |= bit;
+            }
+        }
+    }
+
+    /// Steps `current` into `next` of the same size, treating infinite edges as dead.
+    ///
+    /// Every neighbor of the lights in a word is a word of the row above, the same row or the
+    /// row below, either as it is or shifted by one light. The eight of them are added up bit by
+    /// bit into four words holding the bits of the neighbor counts of all 64 lights.
+    fn step_words(&self, current: &BitGrid, next: &mut BitGrid) {
+        let (width, height, stride) = (current.width, current.height, current.stride);
+        let wrap = self.boundary == Boundary::Toroidal;
+        let last_word_mask = current.last_word_mask();
+        let row = |y: usize| &current.words[y * stride..(y + 1) * stride];
+        // the row above or below `y`, `None` past a dead edge
+        let neighbor_row = |y: usize, dy: isize| -> Option<&[u64]> {
+            match y as isize + dy {
+                ny if ny >= 0 && (ny as usize) < height => Some(row(ny as usize)),
+                _ if wrap => Some(row((y as isize + dy).rem_euclid(height as isize) as usize)),
+                _ => None,
+            }
+        };
+        // the words at `idx` holding the west and east neighbors of each light
+        let shifted = |row: &[u64], idx: usize| -> (u64, u64) {
+            let mut west = row[idx] << 1;
+            let mut east = row[idx] >> 1;
+            if idx > 0 {
+                west |= row[idx - 1] >> 63;
+            } else if wrap {
+                west |= (row[(width - 1) / 64] >> ((width - 1) % 64)) & 1;
+            }
+            if idx + 1 < stride {
+                east |= row[idx + 1] << 63;
+            }
+            if wrap && idx == (width - 1) / 64 {
+                east |= (row[0] & 1) << ((width - 1) % 64);
+            }
+            (west, east)
+        };
+        for y in 0..height {
+            let above = neighbor_row(y, -1);
+            let below = neighbor_row(y, 1);
+            let middle = row(y);
+            for idx in 0..stride {
+                let mut counts = [0u64; 4];
+                let mut add = |mut carry: u64| {
+                    for plane in counts.iter_mut() {
+                        let overflow = *plane & carry;
+                        *plane ^= carry;
+                        carry = overflow;
+                    }
+                };
+                for row in [above, below].into_iter().flatten() {
+                    let (west, east) = shifted(row, idx);
+                    add(west);
+                    add(row[idx]);
+                    add(east);
+                }
+                let (west, east) = shifted(middle, idx);
+                add(west);
+                add(east);
+
+                let alive = middle[idx];
+                let mut word = 0;
+                for neighbors in 0..=8 {
+                    let birth = self.rule.next(false, neighbors);
+                    let survival = self.rule.next(true, neighbors);
+                    if !birth && !survival {
                         continue;
                     }
-                    if grid[y as usize][x as usize] {
-                        alive_neighbors += 1;
+                    let matches = (0..4).fold(!0, |matches, bit| {
+                        matches
+                            & match neighbors >> bit & 1 {
+                                1 => counts[bit],
+                                _ => !counts[bit],
+                            }
+                    });
+                    if birth {
+                        word |= matches & !alive;
                     }
+                    if survival {
+                        word |= matches & alive;
+                    }
+                }
+                if idx + 1 == stride {
+                    word &= last_word_mask;
                 }
+                next.words[y * stride + idx] = word;
             }
+        }
+        self.pin_packed(next);
+    }
+}
 
-            next[y][x] = match grid[y][x] {
-                // A light which is on stays on when 2 or 3 neighbors are on, and turns off otherwise.
-                true => alive_neighbors == 2 || alive_neighbors == 3,
-                // A light which is off turns on if exactly 3 neighbors are on, and stays off otherwise.
-                false => alive_neighbors == 3,
+/// How a run ended up repeating itself, found by [`Automaton::fast_forward`]
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Cycle {
+    /// First step of the cycle
+    pub start: u64,
+    /// Steps until the grid repeats, `1` for a still life
+    pub period: u64,
+    /// How far the lights move each period, which only spaceships on an infinite grid do
+    pub offset: (i64, i64),
+}
+
+/// The grid after a number of steps and the cycle it fell into on the way, if any
+#[derive(PartialEq, Debug, Clone)]
+pub struct Run {
+    pub grid: Grid,
+    pub cycle: Option<Cycle>,
+}
+
+impl Automaton {
+    /// Same as [`Automaton::run`], jumping ahead as soon as the grid repeats itself.
+    ///
+    /// Cycles are found with [Brent's algorithm](https://en.wikipedia.org/wiki/Cycle_detection#Brent's_algorithm),
+    /// which compares the current grid with a single saved one rather than remembering every
+    /// grid seen so far. On an infinite grid without pinned lights the same lights at another
+    /// place count as a repeat, so spaceships are fast-forwarded as well.
+    pub fn fast_forward(&self, grid: &Grid, steps: u64) -> Run {
+        let translates = self.boundary == Boundary::Infinite && self.pinned.is_empty();
+        let same = |a: &BitGrid, b: &BitGrid| {
+            a.width == b.width
+                && a.height == b.height
+                && a.words == b.words
+                && (translates || a.origin == b.origin)
+        };
+        let start = BitGrid::from(&self.with_pins(grid));
+        if steps == 0 {
+            return Run {
+                grid: Grid::from(&start),
+                cycle: None,
+            };
+        }
+        // find the period: the tortoise waits at powers of two for the hare to come by
+        let (mut power, mut period) = (1, 1);
+        let mut tortoise = start.clone();
+        let mut hare = self.step_packed(&start);
+        let mut hare_steps = 1;
+        while !same(&tortoise, &hare) {
+            if hare_steps == steps {
+                return Run {
+                    grid: Grid::from(&hare),
+                    cycle: None,
+                };
+            }
+            if power == period {
+                tortoise = hare.clone();
+                power *= 2;
+                period = 0;
             }
+            hare = self.step_packed(&hare);
+            hare_steps += 1;
+            period += 1;
+        }
+        // find the start: run again with the hare a period ahead until they meet
+        let mut tortoise = start.clone();
+        let mut hare = start;
+        for _ in 0..period {
+            hare = self.step_packed(&hare);
+        }
+        let mut cycle_start = 0;
+        while !same(&tortoise, &hare) {
+            tortoise = self.step_packed(&tortoise);
+            hare = self.step_packed(&hare);
+            cycle_start += 1;
+        }
+        let offset = (
+            hare.origin.0 - tortoise.origin.0,
+            hare.origin.1 - tortoise.origin.1,
+        );
+        let laps = ((steps - cycle_start) / period) as i64;
+        let mut grid = tortoise;
+        for _ in 0..(steps - cycle_start) % period {
+            grid = self.step_packed(&grid);
+        }
+        grid.origin.0 += offset.0 * laps;
+        grid.origin.1 += offset.1 * laps;
+        Run {
+            grid: Grid::from(&grid),
+            cycle: Some(Cycle {
+                start: cycle_start,
+                period,
+                offset,
+            }),
         }
     }
-    next
 }
 
-fn create_grid(width: usize, height: usize) -> Vec<Vec<bool>> {
-    let mut rows = Vec::new();
-    for _ in 0..height {
-        rows.push(vec![false; width]);
+/// Children of a [`HashLife`] node, each a quarter of its square
+#[derive(PartialEq, Eq, Hash, Clone, Copy)]
+struct Quad {
+    nw: u32,
+    ne: u32,
+    sw: u32,
+    se: u32,
+}
+
+struct Node {
+    quad: Quad,
+    /// the node is a square of `2^level` lights
+    level: u8,
+    population: u64,
+}
+
+/// An infinite grid as a quadtree where equal squares are stored only once and remember what
+/// they turn into, the [Hashlife](https://en.wikipedia.org/wiki/Hashlife) algorithm.
+///
+/// Patterns which keep repeating themselves, even while growing without end, can be advanced
+/// by astronomical numbers of steps. Pinned lights aren't supported.
+pub struct HashLife {
+    rule: Rule,
+    /// nodes by id, the first two being single lights which are off and on
+    nodes: Vec<Node>,
+    ids: HashMap<Quad, u32>,
+    /// empty square of each level
+    empty: Vec<u32>,
+    /// centre half of a node after `2^j` steps, by node and `j`
+    successors: HashMap<(u32, u8), u32>,
+    root: u32,
+    /// coordinates of the top left light of the root
+    origin: (i64, i64),
+    generation: u64,
+}
+
+impl HashLife {
+    /// Fails for a rule which turns on lights without neighbors
+    pub fn new(rule: Rule, grid: &Grid) -> anyhow::Result<Self> {
+        if rule.next(false, 0) {
+            bail!("{} can't run on an infinite grid", rule);
+        }
+        let leaf = |population| Node {
+            quad: Quad {
+                nw: 0,
+                ne: 0,
+                sw: 0,
+                se: 0,
+            },
+            level: 0,
+            population,
+        };
+        let mut life = HashLife {
+            rule,
+            nodes: vec![leaf(0), leaf(1)],
+            ids: HashMap::new(),
+            empty: vec![0],
+            successors: HashMap::new(),
+            root: 0,
+            origin: grid.origin,
+            generation: 0,
+        };
+        let mut level = 2;
+        while 1 << level < grid.width.max(grid.height) {
+            level += 1;
+        }
+        life.root = life.build(grid, grid.origin, level);
+        Ok(life)
+    }
+
+    /// Number of steps taken so far
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn count_on(&self) -> u64 {
+        self.nodes[self.root as usize].population
+    }
+
+    fn build(&mut self, grid: &Grid, (x, y): (i64, i64), level: u8) -> u32 {
+        if level == 0 {
+            return grid.get(x, y) as u32;
+        }
+        let half = 1 << (level - 1);
+        let nw = self.build(grid, (x, y), level - 1);
+        let ne = self.build(grid, (x + half, y), level - 1);
+        let sw = self.build(grid, (x, y + half), level - 1);
+        let se = self.build(grid, (x + half, y + half), level - 1);
+        self.join(Quad { nw, ne, sw, se })
+    }
+
+    /// The node made of the four given ones, all of the same level
+    fn join(&mut self, quad: Quad) -> u32 {
+        if let Some(id) = self.ids.get(&quad) {
+            return *id;
+        }
+        let population = [quad.nw, quad.ne, quad.sw, quad.se]
+            .iter()
+            .map(|id| self.nodes[*id as usize].population)
+            .sum();
+        let level = self.nodes[quad.nw as usize].level + 1;
+        self.nodes.push(Node {
+            quad,
+            level,
+            population,
+        });
+        let id = self.nodes.len() as u32 - 1;
+        self.ids.insert(quad, id);
+        id
+    }
+
+    fn empty(&mut self, level: u8) -> u32 {
+        while self.empty.len() <= level as usize {
+            let child = *self.empty.last().unwrap();
+            let id = self.join(Quad {
+                nw: child,
+                ne: child,
+                sw: child,
+                se: child,
+            });
+            self.empty.push(id);
+        }
+        self.empty[level as usize]
+    }
+
+    fn quad(&self, id: u32) -> Quad {
+        self.nodes[id as usize].quad
+    }
+
+    /// The square of half the size in the middle of a node
+    fn centre(&mut self, id: u32) -> u32 {
+        let Quad { nw, ne, sw, se } = self.quad(id);
+        let quad = Quad {
+            nw: self.quad(nw).se,
+            ne: self.quad(ne).sw,
+            sw: self.quad(sw).ne,
+            se: self.quad(se).nw,
+        };
+        self.join(quad)
+    }
+
+    /// Surrounds the root with empty space, doubling its size
+    fn expand(&mut self) {
+        let Quad { nw, ne, sw, se } = self.quad(self.root);
+        let level = self.nodes[self.root as usize].level;
+        let e = self.empty(level - 1);
+        let quad = Quad {
+            nw: self.join(Quad {
+                nw: e,
+                ne: e,
+                sw: e,
+                se: nw,
+            }),
+            ne: self.join(Quad {
+                nw: e,
+                ne: e,
+                sw: ne,
+                se: e,
+            }),
+            sw: self.join(Quad {
+                nw: e,
+                ne: sw,
+                sw: e,
+                se: e,
+            }),
+            se: self.join(Quad {
+                nw: se,
+                ne: e,
+                sw: e,
+                se: e,
+            }),
+        };
+        self.root = self.join(quad);
+        let half = 1 << (level - 1);
+        self.origin = (self.origin.0 - half, self.origin.1 - half);
+    }
+
+    /// Drops empty space around the root while it keeps all lights which are on
+    fn compact(&mut self) {
+        while self.nodes[self.root as usize].level > 2 {
+            let centre = self.centre(self.root);
+            if self.nodes[centre as usize].population != self.count_on() {
+                break;
+            }
+            let quarter = 1 << (self.nodes[self.root as usize].level - 2);
+            self.root = centre;
+            self.origin = (self.origin.0 + quarter, self.origin.1 + quarter);
+        }
+    }
+
+    /// Takes `steps` steps, in jumps of powers of two. Fails without taking any if the generation
+    /// would overflow or the root would have to grow beyond the `i64` coordinates.
+    pub fn advance(&mut self, steps: u64) -> anyhow::Result<()> {
+        if self.generation.checked_add(steps).is_none() {
+            bail!(
+                "{} steps after generation {} overflow",
+                steps,
+                self.generation
+            );
+        }
+        let start = (self.root, self.origin, self.generation);
+        for j in 0..64 {
+            if steps >> j & 1 == 0 {
+                continue;
+            }
+            // with the lights in the centre quarter of the root they can't leave its centre half
+            // in the `2^j <= 2^(level - 3)` steps
+            self.compact();
+            let level = self.nodes[self.root as usize].level;
+            if !self.fits((level + 2).max(j + 3)) {
+                (self.root, self.origin, self.generation) = start;
+                bail!("{} steps take the lights beyond the i64 coordinates", steps);
+            }
+            self.expand();
+            self.expand();
+            while self.nodes[self.root as usize].level < j + 3 {
+                self.expand();
+            }
+            let quarter = 1 << (self.nodes[self.root as usize].level - 2);
+            self.root = self.successor(self.root, j);
+            self.origin = (self.origin.0 + quarter, self.origin.1 + quarter);
+            self.generation += 1 << j;
+        }
+        Ok(())
+    }
+
+    /// Whether the root can grow to `level` around its centre and stay within the `i64`
+    /// coordinates
+    fn fits(&self, level: u8) -> bool {
+        let current = self.nodes[self.root as usize].level;
+        let grow = (1i128 << (level - 1)) - (1i128 << (current - 1));
+        [self.origin.0, self.origin.1].into_iter().all(|origin| {
+            let min = origin as i128 - grow;
+            let max = min + (1i128 << level) - 1;
+            i64::try_from(min).is_ok() && i64::try_from(max).is_ok()
+        })
+    }
+
+    /// The centre half of node `id` after `2^j` steps, with `j <= level - 2`
+    fn successor(&mut self, id: u32, j: u8) -> u32 {
+        if let Some(next) = self.successors.get(&(id, j)) {
+            return *next;
+        }
+        let level = self.nodes[id as usize].level;
+        let next = if self.nodes[id as usize].population == 0 {
+            self.empty(level - 1)
+        } else if level == 2 {
+            self.step_4x4(id)
+        } else {
+            let Quad { nw, ne, sw, se } = self.quad(id);
+            let (qnw, qne, qsw, qse) = (self.quad(nw), self.quad(ne), self.quad(sw), self.quad(se));
+            // nine overlapping squares of half the size
+            let squares = [
+                nw,
+                self.join(Quad {
+                    nw: qnw.ne,
+                    ne: qne.nw,
+                    sw: qnw.se,
+                    se: qne.sw,
+                }),
+                ne,
+                self.join(Quad {
+                    nw: qnw.sw,
+                    ne: qnw.se,
+                    sw: qsw.nw,
+                    se: qsw.ne,
+                }),
+                self.centre(id),
+                self.join(Quad {
+                    nw: qne.sw,
+                    ne: qne.se,
+                    sw: qse.nw,
+                    se: qse.ne,
+                }),
+                sw,
+                self.join(Quad {
+                    nw: qsw.ne,
+                    ne: qse.nw,
+                    sw: qsw.se,
+                    se: qse.sw,
+                }),
+                se,
+            ];
+            // either take both halves of the steps, or none and all of them at the end
+            let full_speed = j == level - 2;
+            let mut c = [0; 9];
+            for (c, square) in c.iter_mut().zip(squares) {
+                *c = match full_speed {
+                    true => self.successor(square, j - 1),
+                    false => self.centre(square),
+                };
+            }
+            let j = if full_speed { j - 1 } else { j };
+            let mut quad = [0; 4];
+            for (quad, corner) in quad.iter_mut().zip([0, 1, 3, 4]) {
+                let joined = self.join(Quad {
+                    nw: c[corner],
+                    ne: c[corner + 1],
+                    sw: c[corner + 3],
+                    se: c[corner + 4],
+                });
+                *quad = self.successor(joined, j);
+            }
+            self.join(Quad {
+                nw: quad[0],
+                ne: quad[1],
+                sw: quad[2],
+                se: quad[3],
+            })
+        };
+        self.successors.insert((id, j), next);
+        next
+    }
+
+    /// The centre 2x2 lights of a 4x4 node after one step
+    fn step_4x4(&mut self, id: u32) -> u32 {
+        let mut lights = [[false; 4]; 4];
+        let Quad { nw, ne, sw, se } = self.quad(id);
+        for (quarter, (x, y)) in [(nw, (0, 0)), (ne, (2, 0)), (sw, (0, 2)), (se, (2, 2))] {
+            let Quad { nw, ne, sw, se } = self.quad(quarter);
+            lights[y][x] = nw == 1;
+            lights[y][x + 1] = ne == 1;
+            lights[y + 1][x] = sw == 1;
+            lights[y + 1][x + 1] = se == 1;
+        }
+        let mut next = [0; 4];
+        for (next, (x, y)) in next.iter_mut().zip([(1, 1), (2, 1), (1, 2), (2, 2)]) {
+            let neighbors = lights[y - 1..=y + 1]
+                .iter()
+                .flat_map(|row| &row[x - 1..=x + 1])
+                .filter(|on| **on)
+                .count()
+                - lights[y][x] as usize;
+            *next = self.rule.next(lights[y][x], neighbors) as u32;
+        }
+        self.join(Quad {
+            nw: next[0],
+            ne: next[1],
+            sw: next[2],
+            se: next[3],
+        })
+    }
+
+    /// The smallest grid holding all lights which are on
+    pub fn grid(&self) -> Grid {
+        let mut cells = vec![];
+        let mut stack = vec![(self.root, self.origin)];
+        while let Some((id, (x, y))) = stack.pop() {
+            let node = &self.nodes[id as usize];
+            if node.population == 0 {
+                continue;
+            }
+            if node.level == 0 {
+                cells.push((x, y));
+                continue;
+            }
+            let half = 1 << (node.level - 1);
+            let Quad { nw, ne, sw, se } = node.quad;
+            stack.push((nw, (x, y)));
+            stack.push((ne, (x + half, y)));
+            stack.push((sw, (x, y + half)));
+            stack.push((se, (x + half, y + half)));
+        }
+        let (mut min, mut max) = ((i64::MAX, i64::MAX), (i64::MIN, i64::MIN));
+        for (x, y) in &cells {
+            min = (min.0.min(*x), min.1.min(*y));
+            max = (max.0.max(*x), max.1.max(*y));
+        }
+        if cells.is_empty() {
+            return Grid::with_origin(self.origin, 0, 0);
+        }
+        let mut grid = Grid::with_origin(
+            min,
+            (max.0 - min.0 + 1) as usize,
+            (max.1 - min.1 + 1) as usize,
+        );
+        for (x, y) in cells {
+            grid.set(x, y, true);
+        }
+        grid
     }
-    rows
 }
 
-fn count_on(grid: &[Vec<bool>]) -> usize {
-    grid.iter()
-        .map(|row| row.iter().filter(|col| **col).count())
-        .sum()
+/// Reads the [plaintext](https://conwaylife.com/wiki/Plaintext) format, where `O` is on, `.` is
+/// off, lines starting with `!` are comments and rows may leave out lights which are off at
+/// their end
+pub fn parse_cells(input: &str) -> anyhow::Result<Grid> {
+    let rows: Vec<(usize, &str)> = input
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.starts_with('!'))
+        .collect();
+    let width = rows.iter().map(|(_, row)| row.len()).max().unwrap_or(0);
+    let mut grid = Grid::new(width, rows.len());
+    for (y, (line, row)) in rows.iter().enumerate() {
+        for (x, c) in row.chars().enumerate() {
+            match c {
+                'O' => grid.set(x as i64, y as i64, true),
+                '.' => {}
+                _ => bail!("line {}: unexpected {:?}", line + 1, c),
+            }
+        }
+    }
+    Ok(grid)
 }
 
-/// Part 2: In your grid of 100x100 lights, given your initial configuration, but with the four
-/// corners always in the on state, how many lights are on after 100 steps?
-#[aoc(day18, part2)]
-fn part2(input: &[Vec<bool>]) -> usize {
-    let grid = evolve2(input, 100);
-    count_on(&grid)
+/// Writes the plaintext format, with a `!Name:` comment if given a name
+pub fn to_cells(grid: &Grid, name: Option<&str>) -> String {
+    let mut cells = String::new();
+    if let Some(name) = name {
+        cells += &format!("!Name: {}\n", name);
+    }
+    for row in grid.to_string().lines() {
+        cells += &row.replace('#', "O");
+        cells.push('\n');
+    }
+    cells
 }
 
-fn evolve2(input: &[Vec<bool>], steps: usize) -> Vec<Vec<bool>> {
-    let mut next = Vec::from(input);
-    for _ in 0..steps {
-        fill_corners(&mut next);
-        next = execute_step(&next);
+/// Reads the [run length encoded](https://conwaylife.com/wiki/Run_Length_Encoded) format with its
+/// rule, Life if there is none. Honours `#P` and `#R` lines placing the top left corner.
+pub fn parse_rle(input: &str) -> anyhow::Result<(Grid, Rule)> {
+    let mut origin = (0, 0);
+    let mut lines = input.lines().enumerate();
+    let header = loop {
+        let line = match lines.next() {
+            Some((_, line)) => line,
+            None => bail!("missing the x = .., y = .. header"),
+        };
+        if let Some(position) = line.strip_prefix("#P").or_else(|| line.strip_prefix("#R")) {
+            let numbers: Vec<i64> = position
+                .split_whitespace()
+                .filter_map(|number| number.parse().ok())
+                .collect();
+            if let [x, y] = numbers[..] {
+                origin = (x, y);
+            }
+        }
+        if !line.starts_with('#') {
+            break line;
+        }
+    };
+    let (mut width, mut height, mut rule) = (None, None, Rule::LIFE);
+    for field in header.split(',') {
+        let (key, value) = field
+            .split_once('=')
+            .ok_or_else(|| anyhow!("header: expected key = value but got {:?}", field))?;
+        let value = value.trim();
+        match key.trim() {
+            "x" => width = Some(value.parse::<usize>()?),
+            "y" => height = Some(value.parse::<usize>()?),
+            // the older S/B notation has no letters, like 23/3
+            "rule" if value.starts_with(|c: char| c.is_ascii_digit() || c == '/') => {
+                let (survival, birth) = value.split_once('/').unwrap_or((value, ""));
+                rule = format!("B{}/S{}", birth, survival).parse()?;
+            }
+            "rule" => rule = value.parse()?,
+            key => bail!("header: unknown key {:?}", key),
+        }
     }
-    fill_corners(&mut next);
-    next
+    let (width, height) = match (width, height) {
+        (Some(width), Some(height)) => (width, height),
+        _ => bail!("header: x and y are required"),
+    };
+    let mut grid = Grid::with_origin(origin, width, height);
+    let (mut x, mut y) = (0, 0);
+    'lines: for (line, text) in lines {
+        let mut count = 0;
+        for c in text.chars() {
+            let run = count.max(1);
+            match c {
+                '0'..='9' => {
+                    count = count * 10 + c.to_digit(10).unwrap() as usize;
+                    continue;
+                }
+                'b' | '.' => x += run,
+                'o' => {
+                    if x + run > width || y >= height {
+                        bail!("line {}: lights outside of {}x{}", line + 1, width, height);
+                    }
+                    for x in x..x + run {
+                        grid.cells[y * width + x] = true;
+                    }
+                    x += run;
+                }
+                '$' => (x, y) = (0, y + run),
+                '!' => break 'lines,
+                c if c.is_whitespace() => {}
+                _ => bail!("line {}: unexpected {:?}", line + 1, c),
+            }
+            count = 0;
+        }
+    }
+    Ok((grid, rule))
 }
 
-/// implements "Four lights, one in each corner, are stuck on and can't be turned off." on grid
-fn fill_corners(grid: &mut [Vec<bool>]) {
-    let width = grid[0].len();
-    let height = grid.len();
-    grid[0][0] = true;
-    grid[0][width - 1] = true;
-    grid[height - 1][0] = true;
-    grid[height - 1][width - 1] = true;
+/// Writes the run length encoded format, with lines of at most 70 characters
+pub fn to_rle(grid: &Grid, rule: &Rule) -> String {
+    let mut rle = String::new();
+    if grid.origin != (0, 0) {
+        rle += &format!("#R {} {}\n", grid.origin.0, grid.origin.1);
+    }
+    rle += &format!("x = {}, y = {}, rule = {}\n", grid.width, grid.height, rule);
+    let mut tokens = vec![];
+    let mut push = |count: usize, tag: char| match count {
+        0 => {}
+        1 => tokens.push(tag.to_string()),
+        _ => tokens.push(format!("{}{}", count, tag)),
+    };
+    let mut rows_ended = 0;
+    for row in grid.cells.chunks(grid.width.max(1)) {
+        // lights which are off at the end of a row are left out
+        let length = row.iter().rposition(|on| *on).map_or(0, |last| last + 1);
+        if length > 0 {
+            push(rows_ended, '$');
+            rows_ended = 0;
+        }
+        let mut x = 0;
+        while x < length {
+            let run = row[x..length]
+                .iter()
+                .take_while(|on| **on == row[x])
+                .count();
+            push(run, if row[x] { 'o' } else { 'b' });
+            x += run;
+        }
+        rows_ended += 1;
+    }
+    tokens.push("!".into());
+    let mut line = String::new();
+    for token in tokens {
+        if line.len() + token.len() > 70 {
+            rle += &line;
+            rle.push('\n');
+            line.clear();
+        }
+        line += &token;
+    }
+    rle += &line;
+    rle.push('\n');
+    rle
+}
+
+/// The grid as a plain [PBM](https://netpbm.sourceforge.net/doc/pbm.html) image, `1` being a
+/// light which is on
+pub fn to_pbm(grid: &Grid) -> String {
+    let mut pbm = format!("P1\n{} {}\n", grid.width, grid.height);
+    for row in grid.cells.chunks(grid.width.max(1)).take(grid.height) {
+        let pixels: Vec<&str> = row.iter().map(|on| if *on { "1" } else { "0" }).collect();
+        pbm += &pixels.join(" ");
+        pbm.push('\n');
+    }
+    pbm
+}
+
+/// Writes the grid and each of the next `steps` generations as `frame_<step>.pbm` images into
+/// `directory`, returning their paths in order
+pub fn write_frames(
+    automaton: &Automaton,
+    grid: &Grid,
+    steps: usize,
+    directory: &Path,
+) -> anyhow::Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(directory)?;
+    let digits = steps.to_string().len();
+    let mut grid = automaton.run(grid, 0);
+    let mut paths = vec![];
+    for step in 0..=steps {
+        if step > 0 {
+            grid = automaton.step(&grid);
+        }
+        let path = directory.join(format!("frame_{:0width$}.pbm", step, width = digits));
+        std::fs::write(&path, to_pbm(&grid))?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+#[aoc_generator(day18)]
+fn parse_input(input: &str) -> Grid {
+    input.parse().unwrap()
+}
+
+/// Part 1: In your grid of 100x100 lights, given your initial configuration,
+/// how many lights are on after 100 steps?
+#[aoc(day18, part1)]
+fn part1(input: &Grid) -> usize {
+    let automaton = Automaton::new(Rule::LIFE, Boundary::Dead).unwrap();
+    automaton.run_packed(&input.into(), 100).count_on()
+}
+
+/// Part 2: In your grid of 100x100 lights, given your initial configuration, but with the four
+/// corners always in the on state, how many lights are on after 100 steps?
+#[aoc(day18, part2)]
+fn part2(input: &Grid) -> usize {
+    let automaton = Automaton::new(Rule::LIFE, Boundary::Dead)
+        .unwrap()
+        .pin(corners(input));
+    automaton.run_packed(&input.into(), 100).count_on()
+}
+
+/// "Four lights, one in each corner, are stuck on and can't be turned off."
+fn corners(grid: &Grid) -> [(i64, i64); 4] {
+    let (left, top) = grid.origin;
+    let right = left + grid.width as i64 - 1;
+    let bottom = top + grid.height as i64 - 1;
+    [(left, top), (right, top), (left, bottom), (right, bottom)]
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_util::Lcg;
 
     const EXAMPLE_1: &str = ".#.#.#
 ...##.
@@ -274,26 +1479,16 @@ mod tests {
 #.#..#
 ####.#";
 
-    fn display_grid(grid: &[Vec<bool>]) -> String {
-        let mut s = String::new();
-        for row in grid.iter() {
-            for col in row {
-                if *col {
-                    s += "#";
-                } else {
-                    s += ".";
-                }
-            }
-            s += "\n";
-        }
-        s.trim().to_string()
-    }
+    const GLIDER: &str = ".#.
+..#
+###";
 
     #[test]
     fn part1_examples() {
         // After `4` steps, this example has four lights on.
-        let grid = evolve1(&parse_input(EXAMPLE_1), 4);
-        assert_eq!(4, count_on(&grid));
+        let automaton = Automaton::new(Rule::LIFE, Boundary::Dead).unwrap();
+        let grid = automaton.run(&parse_input(EXAMPLE_1), 4);
+        assert_eq!(4, grid.count_on());
         assert_eq!(
             "......
 ......
@@ -301,15 +1496,19 @@ mod tests {
 ..##..
 ......
 ......",
-            display_grid(&grid)
+            grid.to_string()
         );
     }
 
     #[test]
     fn part2_examples() {
         // After `5` steps, this example now has `17` lights on.
-        let grid = evolve2(&parse_input(EXAMPLE_2), 5);
-        assert_eq!(17, count_on(&grid));
+        let input = parse_input(EXAMPLE_2);
+        let automaton = Automaton::new(Rule::LIFE, Boundary::Dead)
+            .unwrap()
+            .pin(corners(&input));
+        let grid = automaton.run(&input, 5);
+        assert_eq!(17, grid.count_on());
         assert_eq!(
             "##.###
 .##..#
@@ -317,7 +1516,272 @@ mod tests {
 .##...
 #.#...
 ##...#",
-            display_grid(&grid)
+            grid.to_string()
+        );
+    }
+
+    #[test]
+    fn rules() {
+        assert_eq!("B3/S23".parse::<Rule>().unwrap(), Rule::LIFE);
+        let highlife: Rule = "b36/s23".parse().unwrap();
+        assert_eq!(highlife.to_string(), "B36/S23");
+        assert!(highlife.next(false, 6));
+        assert!(!Rule::LIFE.next(false, 6));
+        assert_eq!("B/S".parse::<Rule>().unwrap().to_string(), "B/S");
+        assert!("B9/S23".parse::<Rule>().is_err());
+        assert!("S23/B3".parse::<Rule>().is_err());
+        assert!("B3S23".parse::<Rule>().is_err());
+    }
+
+    #[test]
+    fn boundaries() {
+        let glider: Grid = GLIDER.parse().unwrap();
+        // a glider moves one light down and to the right every 4 steps
+        let infinite = Automaton::new(Rule::LIFE, Boundary::Infinite).unwrap();
+        let moved = infinite.run(&glider, 4);
+        assert_eq!(moved.origin(), (1, 1));
+        assert_eq!(moved.to_string(), GLIDER);
+        assert_eq!(infinite.run(&glider, 400).origin(), (100, 100));
+
+        // and comes back where it started after crossing a torus
+        let mut grid = Grid::new(6, 5);
+        for (x, y) in glider.live_cells() {
+            grid.set(x, y, true);
+        }
+        let toroidal = Automaton::new(Rule::LIFE, Boundary::Toroidal).unwrap();
+        assert_eq!(toroidal.run(&grid, 4 * 30), grid);
+        assert_ne!(toroidal.run(&grid, 4 * 6), grid);
+
+        // but dies in a corner when the edges are dead
+        let dead = Automaton::new(Rule::LIFE, Boundary::Dead).unwrap();
+        assert_eq!(
+            dead.run(&grid, 20).to_string(),
+            "......
+......
+......
+...##.
+...##."
+        );
+
+        // lights without neighbors turning on would fill an infinite grid at once
+        let b0: Rule = "B0/S".parse().unwrap();
+        assert!(Automaton::new(b0, Boundary::Infinite).is_err());
+        assert!(Automaton::new(b0, Boundary::Toroidal).is_ok());
+    }
+
+    /// Grid with about a third of the lights on, from a linear congruential generator
+    fn random_grid(width: usize, height: usize, seed: u64) -> Grid {
+        let mut random = Lcg::new(seed);
+        let mut grid = Grid::new(width, height);
+        for y in 0..height as i64 {
+            for x in 0..width as i64 {
+                grid.set(x, y, random.next_bits() < (1 << 31) / 3);
+            }
+        }
+        grid
+    }
+
+    #[test]
+    fn packed_agrees_with_grid() {
+        let rules = [
+            Rule::LIFE,
+            "B36/S23".parse().unwrap(),
+            "B2/S".parse().unwrap(),
+        ];
+        let boundaries = [Boundary::Dead, Boundary::Toroidal, Boundary::Infinite];
+        for (seed, (width, height)) in [(1, 1), (3, 2), (64, 5), (70, 9), (130, 4)]
+            .into_iter()
+            .enumerate()
+        {
+            let grid = random_grid(width, height, seed as u64);
+            let packed = BitGrid::from(&grid);
+            assert_eq!(Grid::from(&packed), grid);
+            assert_eq!(packed.count_on(), grid.count_on());
+            for rule in rules {
+                for boundary in boundaries {
+                    let automaton = Automaton::new(rule, boundary)
+                        .unwrap()
+                        .pin([(0, 0), (2, 1)]);
+                    let mut expected = automaton.run(&grid, 0);
+                    let mut actual = automaton.run_packed(&packed, 0);
+                    for _ in 0..6 {
+                        assert_eq!(Grid::from(&actual), expected, "{rule} {boundary:?}");
+                        expected = automaton.step(&expected);
+                        actual = automaton.step_packed(&actual);
+                    }
+                    assert_eq!(automaton.run_packed(&packed, 6), actual);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cycles() {
+        let dead = Automaton::new(Rule::LIFE, Boundary::Dead).unwrap();
+        // After `4` steps, this example has four lights on, which stay on forever
+        let run = dead.fast_forward(&parse_input(EXAMPLE_1), 1_000_000_000);
+        assert_eq!(run.grid, dead.run(&parse_input(EXAMPLE_1), 4));
+        let cycle = run.cycle.unwrap();
+        assert_eq!((cycle.start, cycle.period, cycle.offset), (4, 1, (0, 0)));
+
+        // a blinker in a box
+        let blinker: Grid = ".....\n..#..\n..#..\n..#..\n.....".parse().unwrap();
+        let run = dead.fast_forward(&blinker, 1_000_000_001);
+        assert_eq!(run.grid, dead.step(&blinker));
+        assert_eq!(run.cycle.map(|cycle| cycle.period), Some(2));
+
+        // a glider flies off forever
+        let infinite = Automaton::new(Rule::LIFE, Boundary::Infinite).unwrap();
+        let run = infinite.fast_forward(&GLIDER.parse().unwrap(), 1_000_000_000);
+        assert_eq!(run.grid.origin(), (250_000_000, 250_000_000));
+        assert_eq!(run.grid.to_string(), GLIDER);
+        let cycle = run.cycle.unwrap();
+        assert_eq!((cycle.start, cycle.period, cycle.offset), (0, 4, (1, 1)));
+
+        // fast forwarding agrees with stepping, also before any cycle is found
+        for (input, automaton) in [
+            (EXAMPLE_1, dead.clone()),
+            (
+                EXAMPLE_2,
+                dead.clone().pin(corners(&parse_input(EXAMPLE_2))),
+            ),
+            (
+                EXAMPLE_1,
+                Automaton::new(Rule::LIFE, Boundary::Toroidal).unwrap(),
+            ),
+            (EXAMPLE_2, infinite.clone()),
+            (EXAMPLE_2, infinite.clone().pin([(-3, 2)])),
+        ] {
+            let grid = parse_input(input);
+            for steps in 0..30 {
+                let run = automaton.fast_forward(&grid, steps);
+                assert_eq!(run.grid, automaton.run(&grid, steps as usize));
+            }
+        }
+    }
+
+    #[test]
+    fn hashlife() {
+        for rule in [Rule::LIFE, "B36/S23".parse().unwrap()] {
+            let automaton = Automaton::new(rule, Boundary::Infinite).unwrap();
+            let grid = random_grid(13, 9, 7);
+            let mut life = HashLife::new(rule, &grid).unwrap();
+            let mut expected = grid;
+            for steps in [0, 1, 2, 3, 5, 8, 13, 21] {
+                life.advance(steps).unwrap();
+                expected = automaton.run(&expected, steps as usize);
+                assert_eq!(life.grid(), expected, "{rule} {}", life.generation());
+                assert_eq!(life.count_on(), expected.count_on() as u64);
+            }
+        }
+
+        let mut life = HashLife::new(Rule::LIFE, &GLIDER.parse().unwrap()).unwrap();
+        life.advance(1 << 40).unwrap();
+        assert_eq!(life.grid().origin(), (1 << 38, 1 << 38));
+        assert_eq!(life.grid().to_string(), GLIDER);
+
+        // too far for the coordinates or the generation, which leaves the lights where they were
+        assert!(life.advance(u64::MAX >> 2).is_err());
+        assert!(life.advance(u64::MAX).is_err());
+        assert_eq!(life.generation(), 1 << 40);
+        assert_eq!(life.grid().origin(), (1 << 38, 1 << 38));
+        let glider = GLIDER.parse::<Grid>().unwrap();
+        let mut edge = Grid::with_origin((i64::MAX - 100, 0), 3, 3);
+        for (x, y) in glider.live_cells() {
+            edge.set(i64::MAX - 100 + x, y, true);
+        }
+        let mut life = HashLife::new(Rule::LIFE, &edge).unwrap();
+        assert!(life.advance(1000).is_err());
+        assert_eq!(life.grid(), edge);
+
+        assert!(HashLife::new("B0/S".parse().unwrap(), &glider).is_err());
+
+        let mut empty = HashLife::new(Rule::LIFE, &Grid::new(0, 0)).unwrap();
+        empty.advance(1000).unwrap();
+        assert_eq!((empty.count_on(), empty.grid().width()), (0, 0));
+    }
+
+    const GOSPER_GLIDER_GUN: &str = "#N Gosper glider gun
+#C This was the first gun discovered.
+x = 36, y = 9, rule = B3/S23
+24bo$22bobo$12b2o6b2o12b2o$11bo3bo4b2o12b2o$2o8bo5bo3b2o$2o8bo3bob2o4b
+obo$10bo5bo7bo$11bo3bo$12b2o!
+";
+
+    #[test]
+    fn rle() {
+        let (gun, rule) = parse_rle(GOSPER_GLIDER_GUN).unwrap();
+        assert_eq!((gun.width(), gun.height(), gun.count_on()), (36, 9, 36));
+        assert_eq!(rule, Rule::LIFE);
+        let written = to_rle(&gun, &rule);
+        assert_eq!(
+            written,
+            GOSPER_GLIDER_GUN
+                .lines()
+                .skip(2)
+                .map(|line| format!("{}\n", line))
+                .collect::<String>()
+        );
+        assert_eq!(parse_rle(&written).unwrap(), (gun, rule));
+
+        // blank rows, a position and the S/B rule notation
+        let (grid, rule) = parse_rle("#R -1 2\nx = 3, y = 4, rule = 23/36\n3o3$o!").unwrap();
+        assert_eq!(grid.origin(), (-1, 2));
+        assert_eq!(grid.to_string(), "###\n...\n...\n#..");
+        assert_eq!(rule.to_string(), "B36/S23");
+        assert_eq!(
+            to_rle(&grid, &rule),
+            "#R -1 2\nx = 3, y = 4, rule = B36/S23\n3o3$o!\n"
+        );
+
+        assert!(parse_rle("bo$2bo$3o!").is_err());
+        assert!(parse_rle("x = 2, y = 1\n3o!").is_err());
+        assert!(parse_rle("x = 3, y = 3\n3A!").is_err());
+    }
+
+    #[test]
+    fn plaintext() {
+        let glider = parse_cells("!Name: Glider\n.O\n..O\nOOO").unwrap();
+        assert_eq!(glider.to_string(), GLIDER);
+        let written = to_cells(&glider, Some("Glider"));
+        assert_eq!(written, "!Name: Glider\n.O.\n..O\nOOO\n");
+        assert_eq!(parse_cells(&written).unwrap(), glider);
+        assert!(parse_cells(".O\n#").is_err());
+    }
+
+    #[test]
+    fn frames() {
+        let pbm = to_pbm(&GLIDER.parse().unwrap());
+        assert_eq!(pbm, "P1\n3 3\n0 1 0\n0 0 1\n1 1 1\n");
+
+        // the stuck corners of part 2
+        let input = parse_input(EXAMPLE_2);
+        let automaton = Automaton::new(Rule::LIFE, Boundary::Dead)
+            .unwrap()
+            .pin(corners(&input));
+        let directory = std::env::temp_dir().join(format!("day18-frames-{}", std::process::id()));
+        let paths = write_frames(&automaton, &input, 10, &directory).unwrap();
+        assert_eq!(paths.len(), 11);
+        assert!(paths[3].ends_with("frame_03.pbm"));
+        let frame = std::fs::read_to_string(&paths[5]).unwrap();
+        assert_eq!(frame, to_pbm(&automaton.run(&input, 5)));
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn pinned_outside() {
+        // a pinned light far away from the grid on an infinite plane
+        let automaton = Automaton::new(Rule::LIFE, Boundary::Infinite)
+            .unwrap()
+            .pin([(5, 0)]);
+        let grid = automaton.run(&"###".parse().unwrap(), 1);
+        assert_eq!(grid.origin(), (1, -1));
+        assert_eq!(
+            grid.to_string(),
+            "#....
+#...#
+#...."
         );
+        assert!(grid.get(5, 0));
     }
 }
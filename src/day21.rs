@@ -71,11 +71,47 @@
 //! he wants. The other rules still apply, and he still only has one of each item.
 //! **What is the most amount of gold you can spend and still lose the fight?**
 
-type Stats = (u64, u64, u64);
-type Item = (u64, u64, u64);
+use crate::json::{number, text};
+use serde_json::Value;
+
+/// The player or the boss
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Combatant {
+    pub hitpoints: u64,
+    pub damage: u64,
+    pub armor: u64,
+}
+
+impl Combatant {
+    pub fn new(hitpoints: u64, damage: u64, armor: u64) -> Self {
+        Combatant {
+            hitpoints,
+            damage,
+            armor,
+        }
+    }
+
+    /// Damage dealt by an attacker each turn is equal to the attacker's damage score minus the
+    /// defender's armor score. An attacker always does at least `1` damage.
+    pub fn hit(&self, defender: &Combatant) -> u64 {
+        self.damage.saturating_sub(defender.armor).max(1)
+    }
+
+    /// Number of attacks it takes to bring `defender` to or below `0` hit points
+    pub fn turns_to_kill(&self, defender: &Combatant) -> u64 {
+        defender.hitpoints.div_ceil(self.hit(defender))
+    }
+
+    /// Whether attacking first wins against `defender`, without playing the fight out: the player
+    /// wins if they need no more attacks than the boss, who only gets to attack once the player
+    /// survived the first turn
+    pub fn beats(&self, defender: &Combatant) -> bool {
+        self.turns_to_kill(defender) <= defender.turns_to_kill(self).max(1)
+    }
+}
 
 #[aoc_generator(day21)]
-fn parse_input(input: &str) -> Stats {
+fn parse_input(input: &str) -> Combatant {
     let numbers: Vec<u64> = input
         .lines()
         .map(|line| {
@@ -83,157 +119,387 @@ fn parse_input(input: &str) -> Stats {
             tokens[1].parse().unwrap()
         })
         .collect();
-    (numbers[0], numbers[1], numbers[2])
+    Combatant::new(numbers[0], numbers[1], numbers[2])
+}
+
+/// Something to buy in the shop
+#[derive(PartialEq, Debug, Clone)]
+pub struct Item {
+    pub name: String,
+    pub cost: u64,
+    pub damage: u64,
+    pub armor: u64,
+}
+
+/// A kind of equipment with how many of its items must be bought
+#[derive(PartialEq, Debug, Clone)]
+pub struct Slot {
+    pub name: String,
+    pub min: usize,
+    pub max: usize,
+    pub items: Vec<Item>,
+}
+
+/// The items bought for a fight
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Loadout {
+    pub items: Vec<Item>,
+}
+
+impl Loadout {
+    pub fn cost(&self) -> u64 {
+        self.items.iter().map(|item| item.cost).sum()
+    }
+
+    pub fn damage(&self) -> u64 {
+        self.items.iter().map(|item| item.damage).sum()
+    }
+
+    pub fn armor(&self) -> u64 {
+        self.items.iter().map(|item| item.armor).sum()
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.items.iter().map(|item| item.name.as_str()).collect()
+    }
+
+    /// The player wearing this loadout
+    pub fn equip(&self, hitpoints: u64) -> Combatant {
+        Combatant::new(hitpoints, self.damage(), self.armor())
+    }
+}
+
+/// The item shop of the puzzle
+///
+/// You must buy exactly one weapon; no dual-wielding. Armor is optional, but you can't use more
+/// than one. You can buy 0-2 rings (at most one for each hand).
+pub const SHOP: &str = r#"[
+    {
+        "name": "Weapons", "min": 1, "max": 1,
+        "items": [
+            { "name": "Dagger", "cost": 8, "damage": 4 },
+            { "name": "Shortsword", "cost": 10, "damage": 5 },
+            { "name": "Warhammer", "cost": 25, "damage": 6 },
+            { "name": "Longsword", "cost": 40, "damage": 7 },
+            { "name": "Greataxe", "cost": 74, "damage": 8 }
+        ]
+    },
+    {
+        "name": "Armor", "min": 0, "max": 1,
+        "items": [
+            { "name": "Leather", "cost": 13, "armor": 1 },
+            { "name": "Chainmail", "cost": 31, "armor": 2 },
+            { "name": "Splintmail", "cost": 53, "armor": 3 },
+            { "name": "Bandedmail", "cost": 75, "armor": 4 },
+            { "name": "Platemail", "cost": 102, "armor": 5 }
+        ]
+    },
+    {
+        "name": "Rings", "min": 0, "max": 2,
+        "items": [
+            { "name": "Damage +1", "cost": 25, "damage": 1 },
+            { "name": "Damage +2", "cost": 50, "damage": 2 },
+            { "name": "Damage +3", "cost": 100, "damage": 3 },
+            { "name": "Defense +1", "cost": 20, "armor": 1 },
+            { "name": "Defense +2", "cost": 40, "armor": 2 },
+            { "name": "Defense +3", "cost": 80, "armor": 3 }
+        ]
+    }
+]"#;
+
+/// Parses a JSON array of slots with `name`, `min`, `max` and `items`, where every item has a
+/// `name`, a `cost` and optionally `damage` and `armor` (default `0`).
+/// The shop only has one of each item, so a slot can't require more items than it has.
+pub fn parse_shop(json: &str) -> anyhow::Result<Vec<Slot>> {
+    let slots: Value = serde_json::from_str(json)?;
+    let slots = slots
+        .as_array()
+        .ok_or_else(|| anyhow!("the shop must be an array of slots"))?;
+    slots
+        .iter()
+        .map(|slot| {
+            let name = text(slot, "name")?;
+            let items = slot["items"]
+                .as_array()
+                .ok_or_else(|| anyhow!("slot {} must have an array of items", name))?
+                .iter()
+                .map(|item| {
+                    Ok(Item {
+                        name: text(item, "name")?,
+                        cost: number(item, "cost", None)?,
+                        damage: number(item, "damage", Some(0))?,
+                        armor: number(item, "armor", Some(0))?,
+                    })
+                })
+                .collect::<anyhow::Result<Vec<Item>>>()?;
+            let min: usize = number(slot, "min", Some(0))?;
+            let max: usize = number(slot, "max", None)?;
+            if min > max || max > items.len() {
+                bail!(
+                    "slot {} can't take {}-{} of its {} items",
+                    name,
+                    min,
+                    max,
+                    items.len()
+                );
+            }
+            Ok(Slot {
+                name,
+                min,
+                max,
+                items,
+            })
+        })
+        .collect()
 }
 
 /// Part 1: What is the least amount of gold you can spend and still win the fight?
 #[aoc(day21, part1)]
-fn part1(input: &Stats) -> u64 {
-    min_shop_gold(input)
-}
-
-fn min_shop_gold(boss: &Stats) -> u64 {
-    let weapons: Vec<Item> = vec![(8, 4, 0), (10, 5, 0), (25, 6, 0), (40, 7, 0), (74, 8, 0)];
-    let armors: Vec<Item> = vec![(13, 0, 1), (31, 0, 2), (53, 0, 3), (75, 0, 4), (102, 0, 5)];
-    let rings: Vec<Item> = vec![
-        (20, 0, 1),
-        (25, 1, 0),
-        (40, 0, 2),
-        (50, 2, 0),
-        (80, 0, 3),
-        (100, 3, 0),
-    ];
-    // You must buy exactly one weapon; no dual-wielding.
-    let mut best_by_weapon = Vec::new();
-    for weapon in &weapons {
-        let gold = weapon.0;
-        let player = (100, weapon.1, weapon.2);
-        if is_player_win(&player, boss) {
-            best_by_weapon.push(gold);
-            continue;
-        }
-        // Armor is optional, but you can't use more than one.
-        // You can buy 0-2 rings (at most one for each hand).
-        for item in armor_ring_combos(&armors, &rings) {
-            let gold = gold + item.0;
-            let stats = (player.0, player.1 + item.1, player.2 + item.2);
-            if is_player_win(&stats, boss) {
-                best_by_weapon.push(gold);
-                break;
+fn part1(input: &Combatant) -> u64 {
+    let shop = parse_shop(SHOP).unwrap();
+    optimise(&shop, 100, input).cheapest_win.unwrap().cost()
+}
+
+/// Best loadouts found by [`optimise`], `None` when no loadout wins or loses respectively
+#[derive(PartialEq, Debug, Clone)]
+pub struct Optimum {
+    pub cheapest_win: Option<Loadout>,
+    pub priciest_loss: Option<Loadout>,
+}
+
+/// Tries every loadout the shop allows against `boss`, keeping the first one found at each
+/// extreme when several cost the same
+pub fn optimise(shop: &[Slot], player_hitpoints: u64, boss: &Combatant) -> Optimum {
+    let mut optimum = Optimum {
+        cheapest_win: None,
+        priciest_loss: None,
+    };
+    for loadout in loadouts(shop) {
+        let cost = loadout.cost();
+        let best = if loadout.equip(player_hitpoints).beats(boss) {
+            match &optimum.cheapest_win {
+                Some(best) if best.cost() <= cost => continue,
+                _ => &mut optimum.cheapest_win,
             }
-        }
+        } else {
+            match &optimum.priciest_loss {
+                Some(best) if best.cost() >= cost => continue,
+                _ => &mut optimum.priciest_loss,
+            }
+        };
+        *best = Some(loadout);
+    }
+    optimum
+}
+
+/// Every loadout which follows the rules of all slots, in shop order
+pub fn loadouts(shop: &[Slot]) -> Vec<Loadout> {
+    let mut loadouts = vec![Loadout::default()];
+    for slot in shop {
+        let choices = choices(&slot.items, slot.min, slot.max);
+        loadouts = loadouts
+            .iter()
+            .flat_map(|loadout| {
+                choices.iter().map(move |choice| {
+                    let mut items = loadout.items.clone();
+                    items.extend(choice.iter().map(|item| (*item).clone()));
+                    Loadout { items }
+                })
+            })
+            .collect();
     }
-    *best_by_weapon.iter().min().unwrap()
+    loadouts
 }
 
-// You can buy 0-2 rings (at most one for each hand).
-fn ring_combos(rings: &[Item]) -> Vec<Item> {
-    let mut combos = Vec::new();
-    for a in rings {
-        combos.push(*a);
-        for b in rings {
-            if a != b {
-                combos.push(combine(a, b));
+/// All ways to pick between `min` and `max` different items
+fn choices(items: &[Item], min: usize, max: usize) -> Vec<Vec<&Item>> {
+    let mut choices = vec![];
+    let mut stack: Vec<(usize, Vec<&Item>)> = vec![(0, vec![])];
+    while let Some((next, chosen)) = stack.pop() {
+        if chosen.len() >= min {
+            choices.push(chosen.clone());
+        }
+        if chosen.len() < max {
+            for (idx, item) in items.iter().enumerate().skip(next).rev() {
+                let mut chosen = chosen.clone();
+                chosen.push(item);
+                stack.push((idx + 1, chosen));
             }
         }
     }
-    combos.sort_by(|a, b| a.0.cmp(&b.0));
-    combos
+    choices
 }
 
-fn combine(a: &Item, b: &Item) -> Item {
-    (a.0 + b.0, a.1 + b.1, a.2 + b.2)
+/// A fight played out turn by turn
+#[derive(PartialEq, Debug, Clone)]
+pub struct Fight {
+    pub player_wins: bool,
+    /// One line per attack in the format of the puzzle example
+    pub transcript: Vec<String>,
 }
 
-// Combine ring options with armor
-fn armor_ring_combos(armors: &[Item], rings: &[Item]) -> Vec<Item> {
-    let rings = ring_combos(rings);
-    let mut combos = rings.clone();
-    for armor in armors {
-        combos.push(*armor);
-        for ring in &rings {
-            combos.push(combine(armor, ring));
-        }
-    }
-    combos.sort_by(|a, b| a.0.cmp(&b.0));
-    combos
-}
-
-fn is_player_win(player: &Stats, boss: &Stats) -> bool {
-    let mut player_turn = true;
-    let mut player_hitpoints = player.0 as i64;
-    let player_damage = player.1;
-    let player_armor = player.2;
-    let mut boss_hitpoints = boss.0 as i64;
-    let boss_damage = boss.1;
-    let boss_armor = boss.2;
-
-    loop {
-        if player_turn {
-            let damage = (player_damage - boss_armor).max(1);
-            boss_hitpoints -= damage as i64;
-            if boss_hitpoints <= 0 {
-                return true;
-            }
+/// Plays out the fight between `player` and `boss`, the player always going first
+pub fn fight(player: &Combatant, boss: &Combatant) -> Fight {
+    let mut transcript = vec![];
+    let mut hitpoints = [player.hitpoints as i64, boss.hitpoints as i64];
+    let combatants = [("player", player), ("boss", boss)];
+    for turn in 0.. {
+        let (attacker, attacking) = combatants[turn % 2];
+        let (defender, defending) = combatants[(turn + 1) % 2];
+        let damage = attacking.hit(defending);
+        hitpoints[(turn + 1) % 2] -= damage as i64;
+        let dealt = if defending.armor == 0 || attacking.damage <= defending.armor {
+            format!("{}", damage)
         } else {
-            let damage = (boss_damage - player_armor).max(1);
-            player_hitpoints -= damage as i64;
-            if player_hitpoints <= 0 {
-                return false;
-            }
+            format!("{}-{} = {}", attacking.damage, defending.armor, damage)
+        };
+        transcript.push(format!(
+            "The {} deals {} damage; the {} goes down to {} hit points.",
+            attacker,
+            dealt,
+            defender,
+            hitpoints[(turn + 1) % 2]
+        ));
+        if hitpoints[(turn + 1) % 2] <= 0 {
+            break;
         }
-        player_turn = !player_turn;
+    }
+    Fight {
+        player_wins: hitpoints[1] <= 0,
+        transcript,
     }
 }
 
 /// Part 2: What is the most amount of gold you can spend and still lose the fight?
 #[aoc(day21, part2)]
-fn part2(input: &Stats) -> u64 {
-    max_shop_gold(input)
-}
-
-fn max_shop_gold(boss: &Stats) -> u64 {
-    let mut weapons: Vec<Item> = vec![(8, 4, 0), (10, 5, 0), (25, 6, 0), (40, 7, 0), (74, 8, 0)];
-    weapons.reverse();
-    let mut armors: Vec<Item> = vec![(13, 0, 1), (31, 0, 2), (53, 0, 3), (75, 0, 4), (102, 0, 5)];
-    armors.reverse();
-    let mut rings: Vec<Item> = vec![
-        (20, 0, 1),
-        (25, 1, 0),
-        (40, 0, 2),
-        (50, 2, 0),
-        (80, 0, 3),
-        (100, 3, 0),
-    ];
-    rings.reverse();
-    // You must buy exactly one weapon; no dual-wielding.
-    let mut best_by_weapon = Vec::new();
-    for weapon in &weapons {
-        let gold = weapon.0;
-        let player = (100, weapon.1, weapon.2);
-        // Armor is optional, but you can't use more than one.
-        // You can buy 0-2 rings (at most one for each hand).
-        for item in armor_ring_combos(&armors, &rings).iter().rev() {
-            let gold = gold + item.0;
-            let stats = (player.0, player.1 + item.1, player.2 + item.2);
-            if !is_player_win(&stats, boss) {
-                best_by_weapon.push(gold);
-                break;
-            }
-        }
-    }
-    *best_by_weapon.iter().max().unwrap()
+fn part2(input: &Combatant) -> u64 {
+    let shop = parse_shop(SHOP).unwrap();
+    optimise(&shop, 100, input).priciest_loss.unwrap().cost()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    const EXAMPLE_PLAYER: Stats = (8, 5, 5);
-    const EXAMPLE_BOSS: Stats = (12, 7, 2);
+    const EXAMPLE_PLAYER: Combatant = Combatant {
+        hitpoints: 8,
+        damage: 5,
+        armor: 5,
+    };
+    const EXAMPLE_BOSS: Combatant = Combatant {
+        hitpoints: 12,
+        damage: 7,
+        armor: 2,
+    };
 
     #[test]
     fn part1_examples() {
-        assert_eq!(true, is_player_win(&EXAMPLE_PLAYER, &EXAMPLE_BOSS));
+        assert!(EXAMPLE_PLAYER.beats(&EXAMPLE_BOSS));
+        let fight = fight(&EXAMPLE_PLAYER, &EXAMPLE_BOSS);
+        assert!(fight.player_wins);
+        assert_eq!(
+            fight.transcript,
+            [
+                "The player deals 5-2 = 3 damage; the boss goes down to 9 hit points.",
+                "The boss deals 7-5 = 2 damage; the player goes down to 6 hit points.",
+                "The player deals 5-2 = 3 damage; the boss goes down to 6 hit points.",
+                "The boss deals 7-5 = 2 damage; the player goes down to 4 hit points.",
+                "The player deals 5-2 = 3 damage; the boss goes down to 3 hit points.",
+                "The boss deals 7-5 = 2 damage; the player goes down to 2 hit points.",
+                "The player deals 5-2 = 3 damage; the boss goes down to 0 hit points.",
+            ]
+        );
+    }
+
+    #[test]
+    fn closed_form_agrees_with_fight() {
+        let mut combatants = vec![];
+        for hitpoints in 0..=12 {
+            for damage in 0..=6 {
+                for armor in 0..=4 {
+                    combatants.push(Combatant::new(hitpoints, damage, armor));
+                }
+            }
+        }
+        for player in &combatants {
+            for boss in &combatants {
+                let fight = fight(player, boss);
+                assert_eq!(player.beats(boss), fight.player_wins, "{player:?} {boss:?}");
+                let attacks = fight.transcript.len() as u64;
+                if fight.player_wins {
+                    assert_eq!(player.turns_to_kill(boss).max(1), attacks.div_ceil(2));
+                } else {
+                    assert_eq!(boss.turns_to_kill(player).max(1), attacks / 2);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fight_transcript() {
+        let player = Combatant::new(2, 1, 0);
+        let boss = Combatant::new(5, 3, 4);
+        let fight = fight(&player, &boss);
+        assert!(!fight.player_wins);
+        assert_eq!(
+            fight.transcript,
+            [
+                "The player deals 1 damage; the boss goes down to 4 hit points.",
+                "The boss deals 3 damage; the player goes down to -1 hit points.",
+            ]
+        );
+    }
+
+    #[test]
+    fn puzzle_shop() {
+        let shop = parse_shop(SHOP).unwrap();
+        let names: Vec<&str> = shop.iter().map(|slot| slot.name.as_str()).collect();
+        assert_eq!(names, ["Weapons", "Armor", "Rings"]);
+        // 5 weapons, times no armor or one of 5, times no ring, one of 6 or two of 6
+        assert_eq!(loadouts(&shop).len(), 5 * 6 * (1 + 6 + 15));
+
+        let boss = Combatant::new(103, 9, 2);
+        let optimum = optimise(&shop, 100, &boss);
+        let win = optimum.cheapest_win.unwrap();
+        assert_eq!(win.cost(), 121);
+        assert!(fight(&win.equip(100), &boss).player_wins);
+        let loss = optimum.priciest_loss.unwrap();
+        assert_eq!(loss.cost(), 201);
+        assert!(!fight(&loss.equip(100), &boss).player_wins);
+    }
+
+    #[test]
+    fn custom_shop() {
+        let shop = parse_shop(
+            r#"[
+                { "name": "Hands", "min": 1, "max": 2, "items": [
+                    { "name": "Club", "cost": 5, "damage": 3 },
+                    { "name": "Buckler", "cost": 7, "armor": 2 },
+                    { "name": "Knife", "cost": 2, "damage": 1 }
+                ] },
+                { "name": "Charms", "max": 1, "items": [
+                    { "name": "Lucky coin", "cost": 30, "damage": 1, "armor": 1 }
+                ] }
+            ]"#,
+        )
+        .unwrap();
+        // 3 single items or 3 pairs in hand, with or without the coin
+        assert_eq!(loadouts(&shop).len(), 12);
+        let optimum = optimise(&shop, 8, &Combatant::new(6, 5, 1));
+        assert_eq!(optimum.cheapest_win.unwrap().names(), ["Club", "Knife"]);
+        assert_eq!(
+            optimum.priciest_loss.unwrap().names(),
+            ["Buckler", "Knife", "Lucky coin"]
+        );
+        assert_eq!(
+            optimise(&shop, 1, &Combatant::new(100, 100, 0)).cheapest_win,
+            None
+        );
+
+        let too_many =
+            r#"[{ "name": "Rings", "max": 2, "items": [{ "name": "One", "cost": 1 }] }]"#;
+        assert!(parse_shop(too_many).is_err());
+        assert!(parse_shop(r#"[{ "name": "Empty", "max": 0 }]"#).is_err());
+        assert!(parse_shop(r#"{ "Weapons": [] }"#).is_err());
     }
 }
@@ -144,9 +144,83 @@
 //!
 //! With the same starting stats for you and the boss, **what is the least amount of mana you can
 //! spend and still win the fight?**
-use std::sync::{Arc, Mutex};
+use crate::json::{number, text};
+use serde_json::Value;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
 
-type Stats = (u64, u64);
+/// Boss hit points and damage
+pub type Stats = (u64, u64);
+
+/// Rules of the duel besides the spells and the boss stats
+#[derive(PartialEq, Debug, Clone)]
+pub struct Difficulty {
+    pub player_hitpoints: i64,
+    pub player_mana: i64,
+    /// Hit points the player loses at the start of each of their turns, before any effects apply
+    pub drain: i64,
+    /// Reduces the damage of every spell and effect hit, but never below `1`
+    pub boss_armor: i64,
+    /// Mana the player gains at the start of each of their turns, before any effects apply
+    pub mana_regen: i64,
+    pub stacking: Stacking,
+}
+
+/// What happens when casting a spell whose effect is still active
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Stacking {
+    /// The spell can't be cast, as in the puzzle
+    Forbidden,
+    /// The effect starts over with its full timer
+    Refresh,
+    /// Another copy of the effect runs alongside the active one
+    Stack,
+}
+
+impl Difficulty {
+    /// The rules of part 1
+    pub const NORMAL: Difficulty = Difficulty {
+        player_hitpoints: 50,
+        player_mana: 500,
+        drain: 0,
+        boss_armor: 0,
+        mana_regen: 0,
+        stacking: Stacking::Forbidden,
+    };
+    /// The rules of part 2
+    pub const HARD: Difficulty = Difficulty {
+        drain: 1,
+        ..Difficulty::NORMAL
+    };
+
+    /// Damage a spell or effect does to the boss through its armor
+    fn hit(&self, damage: i64) -> i64 {
+        match damage {
+            0 => 0,
+            damage => (damage - self.boss_armor).max(1),
+        }
+    }
+
+    /// Whether spell `idx` can be cast while `effects` are active
+    fn allows(&self, effects: &[(usize, u64)], idx: usize) -> bool {
+        self.stacking != Stacking::Forbidden || effects.iter().all(|(active, _)| *active != idx)
+    }
+
+    /// Starts the effect of spell `idx` following the stacking rules
+    fn start_effect(&self, effects: &mut Vec<(usize, u64)>, idx: usize, effect: &Effect) {
+        if self.stacking == Stacking::Refresh {
+            effects.retain(|(active, _)| *active != idx);
+        }
+        effects.push((idx, effect.turns));
+        effects.sort_unstable();
+    }
+}
+
+impl Default for Difficulty {
+    fn default() -> Self {
+        Difficulty::NORMAL
+    }
+}
 
 #[aoc_generator(day22)]
 fn parse_input(input: &str) -> Stats {
@@ -160,148 +234,751 @@ fn parse_input(input: &str) -> Stats {
     (numbers[0], numbers[1])
 }
 
-type Spell = (i64, i64, i64, u64, u64, i64, u64);
+/// A spell the player can cast
+#[derive(PartialEq, Debug, Clone)]
+pub struct Spell {
+    pub name: String,
+    pub cost: i64,
+    /// Damage instantly dealt to the boss
+    pub damage: i64,
+    /// Hit points instantly healed
+    pub heal: i64,
+    pub effect: Option<Effect>,
+}
+
+/// Effect started by a spell, applied at the start of each turn while its timer runs
+#[derive(PartialEq, Debug, Clone)]
+pub struct Effect {
+    pub turns: u64,
+    /// Damage dealt to the boss each turn
+    pub damage: i64,
+    /// Armor the player has while the effect is active
+    pub armor: i64,
+    /// Mana the player gets each turn
+    pub mana: i64,
+}
+
+/// The spells from the puzzle: Magic Missile, Drain, Shield, Poison, and Recharge
+pub const SPELLBOOK: &str = r#"[
+    { "name": "Magic Missile", "cost": 53, "damage": 4 },
+    { "name": "Drain", "cost": 73, "damage": 2, "heal": 2 },
+    { "name": "Shield", "cost": 113, "effect": { "turns": 6, "armor": 7 } },
+    { "name": "Poison", "cost": 173, "effect": { "turns": 6, "damage": 3 } },
+    { "name": "Recharge", "cost": 229, "effect": { "turns": 5, "mana": 101 } }
+]"#;
 
-const MISSILE: Spell = (53, 4, 0, 0, 0, 0, 0);
-const DRAIN: Spell = (73, 2, 2, 0, 0, 0, 1);
-const SHIELD: Spell = (113, 0, 0, 7, 0, 6, 2);
-const POISON: Spell = (173, 3, 0, 0, 0, 6, 3);
-const RECHARGE: Spell = (229, 0, 0, 0, 101, 5, 4);
-const SPELLS: [Spell; 5] = [MISSILE, DRAIN, SHIELD, POISON, RECHARGE];
+/// Parses a spellbook given as a JSON array of spells like in [`SPELLBOOK`], where every number
+/// but `cost` and `turns` defaults to `0`
+pub fn parse_spellbook(json: &str) -> anyhow::Result<Vec<Spell>> {
+    let spells: Value = serde_json::from_str(json)?;
+    let spells = spells
+        .as_array()
+        .ok_or_else(|| anyhow!("spellbook must be an array"))?;
+    spells
+        .iter()
+        .map(|spell| {
+            let name = text(spell, "name")?;
+            let effect = match &spell["effect"] {
+                Value::Null => None,
+                effect => Some(Effect {
+                    turns: match number(effect, "turns", None)? {
+                        0 => bail!("effect of {} must last at least 1 turn", name),
+                        turns => turns,
+                    },
+                    damage: number(effect, "damage", Some(0))?,
+                    armor: number(effect, "armor", Some(0))?,
+                    mana: number(effect, "mana", Some(0))?,
+                }),
+            };
+            Ok(Spell {
+                name,
+                cost: number(spell, "cost", None)?,
+                damage: number(spell, "damage", Some(0))?,
+                heal: number(spell, "heal", Some(0))?,
+                effect,
+            })
+        })
+        .collect()
+}
 
 /// Part 1: What is the least amount of mana you can spend and still win the fight?
 #[aoc(day22, part1)]
 fn part1(input: &Stats) -> u64 {
-    let least_mana = Arc::new(Mutex::new(u64::MAX));
-    sim(
-        least_mana.clone(),
-        input.0 as i64,
-        input.1,
-        50,
-        500,
-        vec![],
-        true,
-        0,
-        false,
-    );
-    let least_mana = least_mana.lock().unwrap();
-    *least_mana
+    let spellbook = parse_spellbook(SPELLBOOK).unwrap();
+    least_mana(&spellbook, input, &Difficulty::NORMAL)
 }
 
-#[allow(clippy::too_many_arguments)]
-fn sim(
-    least_mana_used: Arc<Mutex<u64>>,
-    mut boss_hitpoints: i64,
-    boss_damage: u64,
-    mut player_hitpoints: i64,
-    mut player_mana: i64,
-    active_spells: Vec<Spell>,
-    player_turn: bool,
-    mana_used: u64,
-    part_two: bool,
-) -> bool {
-    let mut player_armor = 0;
-    if part_two && player_turn {
-        player_hitpoints -= 1;
-        if player_hitpoints <= 0 {
-            return false;
+/// Least mana to spend to win against `boss` with the given spells, or `u64::MAX` if the boss
+/// can't be beaten
+pub fn least_mana(spellbook: &[Spell], boss: &Stats, difficulty: &Difficulty) -> u64 {
+    cheapest_win(spellbook, boss, difficulty).map_or(u64::MAX, |(mana, _)| mana)
+}
+
+/// Cheapest way to win against `boss`: the mana spent and the names of the spells cast in order.
+///
+/// Searches battles at the start of player turns cheapest first
+/// ([Dijkstra](https://en.wikipedia.org/wiki/Dijkstra%27s_algorithm)), only keeping a battle
+/// if it wasn't reached with less mana before, and stops at the first won battle.
+pub fn cheapest_win(
+    spellbook: &[Spell],
+    boss: &Stats,
+    difficulty: &Difficulty,
+) -> Option<(u64, Vec<String>)> {
+    let start = Battle {
+        player_hitpoints: difficulty.player_hitpoints,
+        player_mana: difficulty.player_mana,
+        boss_hitpoints: boss.0 as i64,
+        effects: vec![],
+    };
+    // search nodes as (battle, parent node, spell cast to get here) where `None` is a won battle
+    let mut nodes: Vec<(Option<Battle>, usize, usize)> = vec![(Some(start.clone()), 0, 0)];
+    let mut least_mana: HashMap<Battle, u64> = HashMap::from([(start, 0)]);
+    let mut queue = BinaryHeap::from([Reverse((0, 0))]);
+    while let Some(Reverse((mana, node))) = queue.pop() {
+        let battle = match &nodes[node].0 {
+            Some(battle) if least_mana[battle] < mana => continue,
+            Some(battle) => battle.start_turn(spellbook, difficulty),
+            None => Turn::Won,
+        };
+        let battle = match battle {
+            Turn::Won => {
+                let mut casts = vec![];
+                let mut node = node;
+                while node != 0 {
+                    casts.push(spellbook[nodes[node].2].name.clone());
+                    node = nodes[node].1;
+                }
+                casts.reverse();
+                return Some((mana, casts));
+            }
+            Turn::Lost => continue,
+            Turn::Next(battle) => battle,
+        };
+        for (idx, spell) in spellbook.iter().enumerate() {
+            let mana = mana + spell.cost as u64;
+            let next = match battle.cast(spellbook, difficulty, idx, boss.1) {
+                Some(Turn::Won) => None,
+                Some(Turn::Next(next)) => {
+                    if least_mana.get(&next).is_some_and(|least| *least <= mana) {
+                        continue;
+                    }
+                    least_mana.insert(next.clone(), mana);
+                    Some(next)
+                }
+                _ => continue,
+            };
+            nodes.push((next, node, idx));
+            queue.push(Reverse((mana, nodes.len() - 1)));
         }
     }
-    let mut new_active_spells: Vec<Spell> = vec![];
-    for active_spell in active_spells {
-        if active_spell.5 >= 0 {
-            boss_hitpoints -= active_spell.1;
-            player_hitpoints += active_spell.2;
-            player_armor += active_spell.3;
-            player_mana += active_spell.4 as i64;
-        }
-        let new_active_spell: Spell = (
-            active_spell.0,
-            active_spell.1,
-            active_spell.2,
-            active_spell.3,
-            active_spell.4,
-            active_spell.5 - 1,
-            active_spell.6,
-        );
-        if new_active_spell.5 > 0 {
-            new_active_spells.push(new_active_spell);
+    None
+}
+
+/// State of a fight at the start of a player turn
+#[derive(PartialEq, Eq, Hash, Clone)]
+struct Battle {
+    player_hitpoints: i64,
+    player_mana: i64,
+    boss_hitpoints: i64,
+    /// Active effects as (spell index, remaining turns), sorted
+    effects: Vec<(usize, u64)>,
+}
+
+enum Turn {
+    Won,
+    Lost,
+    Next(Battle),
+}
+
+impl Battle {
+    /// Applies all active effects, returning the armor they give this turn
+    fn tick(&mut self, spellbook: &[Spell], difficulty: &Difficulty) -> i64 {
+        let mut armor = 0;
+        for (spell, timer) in &mut self.effects {
+            let effect = spellbook[*spell].effect.as_ref().unwrap();
+            self.boss_hitpoints -= difficulty.hit(effect.damage);
+            self.player_mana += effect.mana;
+            armor += effect.armor;
+            *timer -= 1;
         }
+        self.effects.retain(|(_, timer)| *timer > 0);
+        armor
     }
-    {
-        let mut least_mana_used = least_mana_used.lock().unwrap();
-        if boss_hitpoints <= 0 {
-            if mana_used < *least_mana_used {
-                *least_mana_used = mana_used;
-            }
-            return true;
+
+    /// Everything which happens at the start of the player turn, before a spell is cast
+    fn start_turn(&self, spellbook: &[Spell], difficulty: &Difficulty) -> Turn {
+        let mut battle = self.clone();
+        battle.player_hitpoints -= difficulty.drain;
+        if battle.player_hitpoints <= 0 {
+            return Turn::Lost;
         }
-        if mana_used >= *least_mana_used {
-            return false;
+        battle.player_mana += difficulty.mana_regen;
+        battle.tick(spellbook, difficulty);
+        if battle.boss_hitpoints <= 0 {
+            return Turn::Won;
         }
-        drop(least_mana_used);
+        Turn::Next(battle)
     }
-    if player_turn {
-        for spell in SPELLS {
-            let mut spell_already_active = false;
-            for new_active_spell in &new_active_spells {
-                if new_active_spell.6 == spell.6 {
-                    spell_already_active = true;
-                    break;
-                }
+
+    /// Casts spell `idx` followed by the boss turn, `None` if the spell can't be cast
+    fn cast(
+        &self,
+        spellbook: &[Spell],
+        difficulty: &Difficulty,
+        idx: usize,
+        boss_damage: u64,
+    ) -> Option<Turn> {
+        let spell = &spellbook[idx];
+        if spell.cost > self.player_mana || !difficulty.allows(&self.effects, idx) {
+            return None;
+        }
+        let mut battle = self.clone();
+        battle.player_mana -= spell.cost;
+        battle.boss_hitpoints -= difficulty.hit(spell.damage);
+        battle.player_hitpoints += spell.heal;
+        if let Some(effect) = &spell.effect {
+            difficulty.start_effect(&mut battle.effects, idx, effect);
+        }
+        if battle.boss_hitpoints <= 0 {
+            return Some(Turn::Won);
+        }
+        let armor = battle.tick(spellbook, difficulty);
+        if battle.boss_hitpoints <= 0 {
+            return Some(Turn::Won);
+        }
+        battle.player_hitpoints -= (boss_damage as i64 - armor).max(1);
+        if battle.player_hitpoints <= 0 {
+            return Some(Turn::Lost);
+        }
+        Some(Turn::Next(battle))
+    }
+}
+
+/// How a replayed fight ended
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Outcome {
+    PlayerWins,
+    BossWins,
+    /// The player ran out of spells to cast before either side died
+    Undecided,
+}
+
+/// A fight replayed by [`replay`]
+#[derive(PartialEq, Debug, Clone)]
+pub struct Replay {
+    pub outcome: Outcome,
+    pub mana_spent: u64,
+    /// Turn by turn log in the format of the puzzle description
+    pub log: String,
+}
+
+/// Replays a fight in which the player casts the named spells in order, failing on the first
+/// cast which breaks the rules
+pub fn replay(
+    spellbook: &[Spell],
+    boss: &Stats,
+    difficulty: &Difficulty,
+    casts: &[&str],
+) -> anyhow::Result<Replay> {
+    let mut player_hitpoints = difficulty.player_hitpoints;
+    let mut player_mana = difficulty.player_mana;
+    let mut boss_hitpoints = boss.0 as i64;
+    let mut effects: Vec<(usize, u64)> = vec![];
+    let mut mana_spent = 0;
+    let mut log: Vec<String> = vec![];
+    let mut casts = casts.iter().enumerate();
+    let outcome = loop {
+        let player_armor = armor(spellbook, &effects);
+        turn_header(
+            &mut log,
+            "Player",
+            player_hitpoints,
+            player_armor,
+            player_mana,
+            boss_hitpoints,
+        );
+        if difficulty.drain != 0 {
+            player_hitpoints -= difficulty.drain;
+            log.push(format!("Player loses {}.", points(difficulty.drain)));
+            if player_hitpoints <= 0 {
+                log.push("This kills the player, and the boss wins.".into());
+                break Outcome::BossWins;
             }
-            let spell_mana_cost = spell.0;
-            if spell_mana_cost <= player_mana && !spell_already_active {
-                let mut a: Vec<Spell> = new_active_spells.clone();
-                a.push(spell);
-                sim(
-                    least_mana_used.clone(),
-                    boss_hitpoints,
-                    boss_damage,
-                    player_hitpoints,
-                    player_mana - spell_mana_cost,
-                    a,
-                    false,
-                    mana_used + spell_mana_cost as u64,
-                    part_two,
-                );
+        }
+        if difficulty.mana_regen != 0 {
+            player_mana += difficulty.mana_regen;
+            log.push(format!("Player regains {} mana.", difficulty.mana_regen));
+        }
+        if tick_effects(
+            spellbook,
+            difficulty,
+            &mut effects,
+            &mut boss_hitpoints,
+            &mut player_mana,
+            &mut log,
+        ) {
+            break Outcome::PlayerWins;
+        }
+        let (nr, name) = match casts.next() {
+            Some(cast) => cast,
+            None => break Outcome::Undecided,
+        };
+        let idx = spellbook
+            .iter()
+            .position(|spell| spell.name == *name)
+            .ok_or_else(|| anyhow!("cast {}: unknown spell {}", nr + 1, name))?;
+        let spell = &spellbook[idx];
+        if !difficulty.allows(&effects, idx) {
+            bail!("cast {}: {} is already active", nr + 1, name);
+        }
+        if spell.cost > player_mana {
+            bail!(
+                "cast {}: {} costs {} mana but only {} is left",
+                nr + 1,
+                name,
+                spell.cost,
+                player_mana
+            );
+        }
+        player_mana -= spell.cost;
+        mana_spent += spell.cost as u64;
+        let damage = difficulty.hit(spell.damage);
+        boss_hitpoints -= damage;
+        player_hitpoints += spell.heal;
+        let mut details = vec![];
+        if damage > 0 {
+            details.push(format!("dealing {} damage", damage));
+        }
+        if spell.heal > 0 {
+            details.push(format!("healing {} hit points", spell.heal));
+        }
+        if let Some(effect) = &spell.effect {
+            if effect.armor > 0 {
+                details.push(format!("increasing armor by {}", effect.armor));
             }
+            difficulty.start_effect(&mut effects, idx, effect);
         }
-    } else {
-        let damage = (boss_damage - player_armor).max(1) as i64;
+        if details.is_empty() {
+            log.push(format!("Player casts {}.", name));
+        } else {
+            log.push(format!(
+                "Player casts {}, {}.",
+                name,
+                details.join(", and ")
+            ));
+        }
+        if boss_hitpoints <= 0 {
+            log.push("This kills the boss, and the player wins.".into());
+            break Outcome::PlayerWins;
+        }
+
+        log.push(String::new());
+        let player_armor = armor(spellbook, &effects);
+        turn_header(
+            &mut log,
+            "Boss",
+            player_hitpoints,
+            player_armor,
+            player_mana,
+            boss_hitpoints,
+        );
+        if tick_effects(
+            spellbook,
+            difficulty,
+            &mut effects,
+            &mut boss_hitpoints,
+            &mut player_mana,
+            &mut log,
+        ) {
+            break Outcome::PlayerWins;
+        }
+        let damage = (boss.1 as i64 - player_armor).max(1);
         player_hitpoints -= damage;
-        if player_hitpoints > 0 {
-            sim(
-                least_mana_used,
-                boss_hitpoints,
-                boss_damage,
-                player_hitpoints,
-                player_mana,
-                new_active_spells,
-                true,
-                mana_used,
-                part_two,
-            );
+        if player_armor > 0 {
+            log.push(format!(
+                "Boss attacks for {} - {} = {} damage!",
+                boss.1, player_armor, damage
+            ));
+        } else {
+            log.push(format!("Boss attacks for {} damage.", damage));
         }
+        if player_hitpoints <= 0 {
+            log.push("This kills the player, and the boss wins.".into());
+            break Outcome::BossWins;
+        }
+        log.push(String::new());
+    };
+    if let Some((nr, _)) = casts.next() {
+        bail!("cast {}: the fight is already over", nr + 1);
+    }
+    Ok(Replay {
+        outcome,
+        mana_spent,
+        log: log.join("\n"),
+    })
+}
+
+/// Checks that casting the named spells in order wins the fight, returning the mana spent
+pub fn validate(
+    spellbook: &[Spell],
+    boss: &Stats,
+    difficulty: &Difficulty,
+    casts: &[&str],
+) -> anyhow::Result<u64> {
+    let replay = replay(spellbook, boss, difficulty, casts)?;
+    match replay.outcome {
+        Outcome::PlayerWins => Ok(replay.mana_spent),
+        Outcome::BossWins => bail!("the boss wins"),
+        Outcome::Undecided => bail!("the fight is not over after the last cast"),
+    }
+}
+
+fn armor(spellbook: &[Spell], effects: &[(usize, u64)]) -> i64 {
+    effects
+        .iter()
+        .filter_map(|(spell, _)| spellbook[*spell].effect.as_ref())
+        .map(|effect| effect.armor)
+        .sum()
+}
+
+fn turn_header(
+    log: &mut Vec<String>,
+    who: &str,
+    player_hitpoints: i64,
+    player_armor: i64,
+    player_mana: i64,
+    boss_hitpoints: i64,
+) {
+    log.push(format!("-- {} turn --", who));
+    log.push(format!(
+        "- Player has {}, {} armor, {} mana",
+        points(player_hitpoints),
+        player_armor,
+        player_mana
+    ));
+    log.push(format!("- Boss has {}", points(boss_hitpoints)));
+}
+
+fn points(hitpoints: i64) -> String {
+    match hitpoints {
+        1 => "1 hit point".to_string(),
+        _ => format!("{} hit points", hitpoints),
     }
-    true
+}
+
+/// Applies all active effects, logging what they do. Returns true if they kill the boss.
+fn tick_effects(
+    spellbook: &[Spell],
+    difficulty: &Difficulty,
+    effects: &mut Vec<(usize, u64)>,
+    boss_hitpoints: &mut i64,
+    player_mana: &mut i64,
+    log: &mut Vec<String>,
+) -> bool {
+    for (spell, timer) in effects.iter_mut() {
+        let name = &spellbook[*spell].name;
+        let effect = spellbook[*spell].effect.as_ref().unwrap();
+        let damage = difficulty.hit(effect.damage);
+        *boss_hitpoints -= damage;
+        *player_mana += effect.mana;
+        *timer -= 1;
+        let mut details = vec![];
+        if damage > 0 {
+            details.push(format!("deals {} damage", damage));
+        }
+        if effect.mana > 0 {
+            details.push(format!("provides {} mana", effect.mana));
+        }
+        if *boss_hitpoints <= 0 {
+            log.push(format!(
+                "{} {}. This kills the boss, and the player wins.",
+                name,
+                details.join(" and ")
+            ));
+            return true;
+        }
+        if details.is_empty() {
+            log.push(format!("{}'s timer is now {}.", name, timer));
+        } else {
+            log.push(format!(
+                "{} {}; its timer is now {}.",
+                name,
+                details.join(" and "),
+                timer
+            ));
+        }
+        if *timer == 0 {
+            if effect.armor > 0 {
+                log.push(format!(
+                    "{} wears off, decreasing armor by {}.",
+                    name, effect.armor
+                ));
+            } else {
+                log.push(format!("{} wears off.", name));
+            }
+        }
+    }
+    effects.retain(|(_, timer)| *timer > 0);
+    false
 }
 
 /// Part 2: What is the least amount of mana you can spend and still win the fight?
 #[aoc(day22, part2)]
 fn part2(input: &Stats) -> u64 {
-    let least_mana = Arc::new(Mutex::new(u64::MAX));
-    sim(
-        least_mana.clone(),
-        input.0 as i64,
-        input.1,
-        50,
-        500,
-        vec![],
-        true,
-        0,
-        true,
-    );
-    let least_mana = least_mana.lock().unwrap();
-    *least_mana
+    let spellbook = parse_spellbook(SPELLBOOK).unwrap();
+    least_mana(&spellbook, input, &Difficulty::HARD)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// For example, suppose the player has `10` hit points and `250` mana
+    const EXAMPLE: Difficulty = Difficulty {
+        player_hitpoints: 10,
+        player_mana: 250,
+        ..Difficulty::NORMAL
+    };
+
+    #[test]
+    fn part1_examples() {
+        let spellbook = parse_spellbook(SPELLBOOK).unwrap();
+        // and that the boss has `13` hit points and `8` damage: Poison, Magic Missile
+        assert_eq!(least_mana(&spellbook, &(13, 8), &EXAMPLE), 173 + 53);
+        // Now, suppose the same initial conditions, except that the boss has `14` hit points
+        // instead: Recharge, Shield, Drain, Poison, Magic Missile
+        assert_eq!(
+            least_mana(&spellbook, &(14, 8), &EXAMPLE),
+            229 + 113 + 73 + 173 + 53
+        );
+    }
+
+    #[test]
+    fn cheapest_win_casts() {
+        let spellbook = parse_spellbook(SPELLBOOK).unwrap();
+        assert_eq!(
+            cheapest_win(&spellbook, &(13, 8), &EXAMPLE),
+            Some((226, vec!["Poison".into(), "Magic Missile".into()]))
+        );
+        let broke = Difficulty {
+            player_mana: 50,
+            ..EXAMPLE
+        };
+        assert_eq!(cheapest_win(&spellbook, &(13, 8), &broke), None);
+    }
+
+    #[test]
+    fn search_finds_valid_cheapest_wins() {
+        let spellbook = parse_spellbook(SPELLBOOK).unwrap();
+        // least mana found by the original depth-first search, at normal and hard difficulty
+        for (boss, normal, hard) in [
+            ((13, 8), 212, 212),
+            ((14, 8), 212, 212),
+            ((30, 8), 332, 332),
+            ((40, 9), 734, 754),
+            ((51, 9), 900, 1216),
+            ((55, 10), 1309, 1309),
+        ] {
+            for (difficulty, expected) in [(Difficulty::NORMAL, normal), (Difficulty::HARD, hard)] {
+                let (mana, casts) = cheapest_win(&spellbook, &boss, &difficulty).unwrap();
+                assert_eq!(mana, expected);
+                let casts: Vec<&str> = casts.iter().map(String::as_str).collect();
+                let spent = validate(&spellbook, &boss, &difficulty, &casts);
+                assert_eq!(spent.unwrap(), mana);
+            }
+        }
+    }
+
+    #[test]
+    fn difficulty_modifiers() {
+        let spellbook = parse_spellbook(SPELLBOOK).unwrap();
+        let boss = (30, 8);
+        let normal = least_mana(&spellbook, &boss, &Difficulty::NORMAL);
+        let hard = least_mana(&spellbook, &boss, &Difficulty::HARD);
+        let variants = [
+            Difficulty {
+                boss_armor: 1,
+                ..Difficulty::NORMAL
+            },
+            Difficulty {
+                mana_regen: 20,
+                drain: 2,
+                ..Difficulty::NORMAL
+            },
+            Difficulty {
+                stacking: Stacking::Refresh,
+                ..Difficulty::HARD
+            },
+            Difficulty {
+                stacking: Stacking::Stack,
+                player_hitpoints: 30,
+                ..Difficulty::HARD
+            },
+        ];
+        // least mana found by the original depth-first search for each variant
+        for (difficulty, expected) in variants.iter().zip([491, 332, 332, 332]) {
+            let (mana, casts) = cheapest_win(&spellbook, &boss, difficulty).unwrap();
+            assert_eq!(mana, expected);
+            let casts: Vec<&str> = casts.iter().map(String::as_str).collect();
+            assert_eq!(
+                validate(&spellbook, &boss, difficulty, &casts).unwrap(),
+                mana
+            );
+        }
+        assert!(least_mana(&spellbook, &boss, &variants[0]) >= normal);
+        assert!(least_mana(&spellbook, &boss, &variants[2]) <= hard);
+        assert!(least_mana(&spellbook, &boss, &Difficulty::default()) == normal);
+    }
+
+    #[test]
+    fn replay_modifiers() {
+        let spellbook = parse_spellbook(SPELLBOOK).unwrap();
+        let difficulty = Difficulty {
+            player_hitpoints: 10,
+            drain: 2,
+            boss_armor: 2,
+            mana_regen: 10,
+            stacking: Stacking::Refresh,
+            ..Difficulty::NORMAL
+        };
+        let replay = replay(&spellbook, &(13, 4), &difficulty, &["Poison", "Poison"]).unwrap();
+        assert_eq!(replay.outcome, Outcome::BossWins);
+        assert!(replay.log.starts_with(
+            "-- Player turn --
+- Player has 10 hit points, 0 armor, 500 mana
+- Boss has 13 hit points
+Player loses 2 hit points.
+Player regains 10 mana.
+Player casts Poison.
+
+-- Boss turn --
+- Player has 8 hit points, 0 armor, 337 mana
+- Boss has 13 hit points
+Poison deals 1 damage; its timer is now 5.
+Boss attacks for 4 damage."
+        ));
+        // recasting Poison restarts its timer
+        assert!(replay.log.ends_with(
+            "Poison deals 1 damage; its timer is now 4.
+Player casts Poison.
+
+-- Boss turn --
+- Player has 2 hit points, 0 armor, 174 mana
+- Boss has 11 hit points
+Poison deals 1 damage; its timer is now 5.
+Boss attacks for 4 damage.
+This kills the player, and the boss wins."
+        ));
+        let error = validate(&spellbook, &(13, 8), &EXAMPLE, &["Poison", "Poison"]).unwrap_err();
+        assert_eq!(error.to_string(), "cast 2: Poison is already active");
+    }
+
+    #[test]
+    fn replay_log() {
+        let spellbook = parse_spellbook(SPELLBOOK).unwrap();
+        let casts = ["Poison", "Magic Missile"];
+        assert_eq!(
+            replay(&spellbook, &(13, 8), &EXAMPLE, &casts).unwrap(),
+            Replay {
+                outcome: Outcome::PlayerWins,
+                mana_spent: 226,
+                log: "-- Player turn --
+- Player has 10 hit points, 0 armor, 250 mana
+- Boss has 13 hit points
+Player casts Poison.
+
+-- Boss turn --
+- Player has 10 hit points, 0 armor, 77 mana
+- Boss has 13 hit points
+Poison deals 3 damage; its timer is now 5.
+Boss attacks for 8 damage.
+
+-- Player turn --
+- Player has 2 hit points, 0 armor, 77 mana
+- Boss has 10 hit points
+Poison deals 3 damage; its timer is now 4.
+Player casts Magic Missile, dealing 4 damage.
+
+-- Boss turn --
+- Player has 2 hit points, 0 armor, 24 mana
+- Boss has 3 hit points
+Poison deals 3 damage. This kills the boss, and the player wins."
+                    .into()
+            }
+        );
+
+        let casts = ["Recharge", "Shield", "Drain", "Poison", "Magic Missile"];
+        let log = replay(&spellbook, &(14, 8), &EXAMPLE, &casts).unwrap().log;
+        assert!(log.contains(
+            "-- Boss turn --
+- Player has 3 hit points, 7 armor, 239 mana
+- Boss has 12 hit points
+Shield's timer is now 3.
+Recharge provides 101 mana; its timer is now 0.
+Recharge wears off.
+Boss attacks for 8 - 7 = 1 damage!"
+        ));
+        assert!(log.contains(
+            "- Player has 1 hit point, 7 armor, 167 mana
+- Boss has 9 hit points
+Shield's timer is now 0.
+Shield wears off, decreasing armor by 7.
+Poison deals 3 damage; its timer is now 4.
+Player casts Magic Missile, dealing 4 damage."
+        ));
+        assert!(log.contains("Player casts Drain, dealing 2 damage, and healing 2 hit points."));
+    }
+
+    #[test]
+    fn validate_casts() {
+        let spellbook = parse_spellbook(SPELLBOOK).unwrap();
+        let casts = ["Recharge", "Shield", "Drain", "Poison", "Magic Missile"];
+        assert_eq!(
+            validate(&spellbook, &(14, 8), &EXAMPLE, &casts).unwrap(),
+            641
+        );
+        let invalid = [
+            (vec!["Poison", "Poison"], "cast 2: Poison is already active"),
+            (vec!["Fireball"], "cast 1: unknown spell Fireball"),
+            (
+                vec!["Recharge", "Recharge"],
+                "cast 2: Recharge is already active",
+            ),
+            (
+                vec!["Poison", "Recharge"],
+                "cast 2: Recharge costs 229 mana but only 77 is left",
+            ),
+            (vec!["Magic Missile", "Magic Missile"], "the boss wins"),
+            (
+                vec!["Poison", "Magic Missile", "Drain"],
+                "cast 3: the fight is already over",
+            ),
+            (vec!["Shield"], "the fight is not over after the last cast"),
+        ];
+        for (casts, message) in invalid {
+            let error = validate(&spellbook, &(13, 8), &EXAMPLE, &casts).unwrap_err();
+            assert_eq!(error.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn custom_spellbook() {
+        let spellbook = parse_spellbook(SPELLBOOK).unwrap();
+        assert_eq!(spellbook.len(), 5);
+        assert_eq!(spellbook[4].effect.as_ref().unwrap().mana, 101);
+
+        let spellbook =
+            parse_spellbook(r#"[{ "name": "Fireball", "cost": 100, "damage": 7 }]"#).unwrap();
+        assert_eq!(least_mana(&spellbook, &(14, 8), &EXAMPLE), 200);
+        let broke = Difficulty {
+            player_mana: 150,
+            ..EXAMPLE
+        };
+        assert_eq!(least_mana(&spellbook, &(14, 8), &broke), u64::MAX);
+
+        assert!(parse_spellbook(r#"[{ "name": "Free" }]"#).is_err());
+        assert!(parse_spellbook(r#"[{ "name": "Slow", "cost": 1, "effect": {} }]"#).is_err());
+        let json = r#"[{ "name": "Blink", "cost": 1, "effect": { "turns": 0 } }]"#;
+        assert!(parse_spellbook(json).is_err());
+    }
 }
@@ -44,32 +44,177 @@
 //! computer. Definitely not to distract you, **what is the value in register b after the program is
 //! finished executing if register a starts as 1 instead?**
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 
 #[aoc_generator(day23)]
-fn parse_input(input: &str) -> Vec<Instruction> {
-    input
-        .lines()
-        .map(|line| {
-            let command = &line[0..3];
-            let args: Vec<&str> = line[4..line.len()].split(", ").collect();
-
-            match command {
-                "hlf" => Instruction::Hlf(args[0].to_owned()),
-                "tpl" => Instruction::Tpl(args[0].to_owned()),
-                "inc" => Instruction::Inc(args[0].to_owned()),
-                "jmp" => Instruction::Jmp(args[0].parse().unwrap()),
-                "jie" => Instruction::Jie(args[0].to_owned(), args[1].parse().unwrap()),
-                "jio" => Instruction::Jio(args[0].to_owned(), args[1].parse().unwrap()),
-                _ => {
-                    panic!("unknown instruction: {}", command)
-                }
+pub fn parse_input(input: &str) -> Vec<Instruction> {
+    assemble(input).unwrap()
+}
+
+/// A problem in assembly source, `line` and `column` are 1-based
+#[derive(PartialEq, Debug, Clone)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Assembles a program, one instruction per line. Everything after a `;` is a comment,
+/// blank lines are ignored and a line may start with a `label:` which jumps can use instead of
+/// an offset, e.g. `jie a, done`.
+pub fn assemble(source: &str) -> Result<Vec<Instruction>, Vec<ParseError>> {
+    let mut code = vec![];
+    let mut errors = vec![];
+    let mut labels: HashMap<&str, usize> = HashMap::new();
+    // jumps to labels as (instruction index, label, line, column)
+    let mut jumps: Vec<(usize, String, usize, usize)> = vec![];
+    for (idx, line) in source.lines().enumerate() {
+        let line_no = idx + 1;
+        let error = |column: usize, message: String| ParseError {
+            line: line_no,
+            column: column + 1,
+            message,
+        };
+        let text = line.split(';').next().unwrap().trim_end();
+        let (mut column, mut text) = skip_whitespace(0, text);
+        if let Some((label, tail)) = text.split_once(':') {
+            if !is_identifier(label) {
+                errors.push(error(column, format!("invalid label '{}'", label)));
+                continue;
+            }
+            if labels.insert(label, code.len()).is_some() {
+                errors.push(error(column, format!("duplicate label '{}'", label)));
+            }
+            (column, text) = skip_whitespace(column + label.len() + 1, tail);
+        }
+        if text.is_empty() {
+            continue;
+        }
+        let (mnemonic, args) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let args = split_args(column + mnemonic.len() + 1, args);
+        let arity = match mnemonic {
+            "hlf" | "tpl" | "inc" | "jmp" => 1,
+            "jie" | "jio" => 2,
+            _ => {
+                errors.push(error(column, format!("unknown instruction '{}'", mnemonic)));
+                continue;
+            }
+        };
+        if args.len() != arity {
+            let message = format!(
+                "{} expects {} argument(s), found {}",
+                mnemonic,
+                arity,
+                args.len()
+            );
+            errors.push(error(column, message));
+            continue;
+        }
+        if let Some((column, _)) = args.iter().find(|(_, arg)| arg.is_empty()) {
+            errors.push(error(*column, "missing argument".into()));
+            continue;
+        }
+        let register = |(column, arg): (usize, &str)| match arg {
+            "a" | "b" => Ok(arg.to_owned()),
+            _ => Err(error(column, format!("unknown register '{}'", arg))),
+        };
+        let mut offset = |(column, arg): (usize, &str)| {
+            if arg.starts_with(['+', '-']) {
+                arg.parse()
+                    .map_err(|_| error(column, format!("invalid offset '{}'", arg)))
+            } else if is_identifier(arg) {
+                jumps.push((code.len(), arg.to_owned(), line_no, column + 1));
+                Ok(0)
+            } else {
+                Err(error(
+                    column,
+                    format!("expected offset like +2 or a label, found '{}'", arg),
+                ))
+            }
+        };
+        let instruction = match mnemonic {
+            "hlf" => register(args[0]).map(Instruction::Hlf),
+            "tpl" => register(args[0]).map(Instruction::Tpl),
+            "inc" => register(args[0]).map(Instruction::Inc),
+            "jmp" => offset(args[0]).map(Instruction::Jmp),
+            "jie" => register(args[0]).and_then(|r| Ok(Instruction::Jie(r, offset(args[1])?))),
+            _ => register(args[0]).and_then(|r| Ok(Instruction::Jio(r, offset(args[1])?))),
+        };
+        match instruction {
+            Ok(instruction) => code.push(instruction),
+            Err(err) => errors.push(err),
+        }
+    }
+    for (idx, label, line, column) in jumps {
+        let target = match labels.get(label.as_str()) {
+            Some(target) => *target as i64 - idx as i64,
+            None => {
+                errors.push(ParseError {
+                    line,
+                    column,
+                    message: format!("unknown label '{}'", label),
+                });
+                continue;
             }
+        };
+        if let Some(
+            Instruction::Jmp(offset) | Instruction::Jie(_, offset) | Instruction::Jio(_, offset),
+        ) = code.get_mut(idx)
+        {
+            *offset = target;
+        }
+    }
+    if errors.is_empty() {
+        Ok(code)
+    } else {
+        errors.sort_by_key(|err| (err.line, err.column));
+        Err(errors)
+    }
+}
+
+/// Prints a program in canonical form, one instruction per line
+pub fn disassemble(code: &[Instruction]) -> String {
+    code.iter()
+        .map(|instruction| format!("{}\n", instruction))
+        .collect()
+}
+
+fn is_identifier(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Skips leading whitespace, keeping track of the 0-based column
+fn skip_whitespace(column: usize, text: &str) -> (usize, &str) {
+    let trimmed = text.trim_start();
+    (column + text.len() - trimmed.len(), trimmed)
+}
+
+/// Splits comma separated arguments starting at `column` into `(column, argument)`
+fn split_args(mut column: usize, args: &str) -> Vec<(usize, &str)> {
+    if args.trim().is_empty() {
+        return vec![];
+    }
+    args.split(',')
+        .map(|arg| {
+            let (start, trimmed) = skip_whitespace(column, arg);
+            column += arg.len() + 1;
+            (start, trimmed.trim_end())
         })
         .collect()
 }
 
-enum Instruction {
+#[derive(PartialEq, Debug, Clone)]
+pub enum Instruction {
     Hlf(String),
     Tpl(String),
     Inc(String),
@@ -78,6 +223,19 @@ enum Instruction {
     Jio(String, i64),
 }
 
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::Hlf(r) => write!(f, "hlf {}", r),
+            Instruction::Tpl(r) => write!(f, "tpl {}", r),
+            Instruction::Inc(r) => write!(f, "inc {}", r),
+            Instruction::Jmp(offset) => write!(f, "jmp {:+}", offset),
+            Instruction::Jie(r, offset) => write!(f, "jie {}, {:+}", r, offset),
+            Instruction::Jio(r, offset) => write!(f, "jio {}, {:+}", r, offset),
+        }
+    }
+}
+
 /// Part 1: What is the value in register `b` when the program in your puzzle input is finished executing?
 #[aoc(day23, part1)]
 fn part1(input: &[Instruction]) -> i64 {
@@ -86,70 +244,287 @@ fn part1(input: &[Instruction]) -> i64 {
 }
 
 fn exec(code: &[Instruction], initial_a: i64) -> HashMap<String, i64> {
-    let mut registers = HashMap::new();
-    registers.insert("a".to_owned(), initial_a);
-    registers.insert("b".to_owned(), 0);
+    let mut machine = Machine::new(code);
+    machine.set_register("a", initial_a);
+    machine.run();
+    machine.registers
+}
 
-    let mut pointer: isize = 0;
+/// Why [`Machine::run`] returned
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Stop {
+    /// The program tried to run an instruction beyond the ones defined
+    Halted,
+    /// The next instruction to run is at this breakpoint
+    Breakpoint(usize),
+    /// The step limit was reached before the program halted, it might never terminate
+    StepLimit,
+}
+
+/// One executed instruction
+#[derive(PartialEq, Debug, Clone)]
+pub struct TraceStep {
+    pub pc: usize,
+    pub instruction: Instruction,
+    /// Registers written by the instruction as `(register, before, after)`
+    pub changes: Vec<(String, i64, i64)>,
+}
 
-    loop {
-        if pointer < 0 || pointer >= code.len() as isize {
-            break;
+impl TraceStep {
+    fn new(pc: usize, instruction: &Instruction, change: Option<(i64, i64)>) -> TraceStep {
+        let changes = match (instruction, change) {
+            (
+                Instruction::Hlf(r) | Instruction::Tpl(r) | Instruction::Inc(r),
+                Some((before, after)),
+            ) => vec![(r.clone(), before, after)],
+            _ => vec![],
+        };
+        TraceStep {
+            pc,
+            instruction: instruction.clone(),
+            changes,
         }
-        let instr = &code[pointer as usize];
-        match instr {
-            // `hlf r` sets register `r` to half its current value,
-            // then continues with the next instruction.
-            Instruction::Hlf(r) => {
-                if let Some(r) = registers.get_mut(r) {
-                    *r /= 2;
-                }
-                pointer += 1;
-            }
-            // `tpl r` sets register `r` to triple its current value,
-            // then continues with the next instruction.
-            Instruction::Tpl(r) => {
-                if let Some(r) = registers.get_mut(r) {
-                    *r *= 3;
+    }
+}
+
+/// The computer running a program, one instruction at a time if needed
+pub struct Machine<'a> {
+    code: &'a [Instruction],
+    registers: HashMap<String, i64>,
+    pointer: isize,
+    steps: usize,
+    step_limit: Option<usize>,
+    breakpoints: HashSet<usize>,
+    trace: Option<Vec<TraceStep>>,
+    /// The breakpoint [`Machine::run`] last stopped at, if nothing has been executed since
+    paused_at: Option<usize>,
+}
+
+impl<'a> Machine<'a> {
+    /// Machine about to run `code` with registers `a` and `b` starting at `0`
+    pub fn new(code: &'a [Instruction]) -> Self {
+        Machine {
+            code,
+            registers: HashMap::from([("a".to_owned(), 0), ("b".to_owned(), 0)]),
+            pointer: 0,
+            steps: 0,
+            step_limit: None,
+            breakpoints: HashSet::new(),
+            trace: None,
+            paused_at: None,
+        }
+    }
+
+    pub fn register(&self, name: &str) -> i64 {
+        self.registers.get(name).copied().unwrap_or(0)
+    }
+
+    pub fn set_register(&mut self, name: &str, value: i64) {
+        self.registers.insert(name.to_owned(), value);
+    }
+
+    pub fn registers(&self) -> &HashMap<String, i64> {
+        &self.registers
+    }
+
+    /// Index of the next instruction, `None` once the program has halted
+    pub fn pc(&self) -> Option<usize> {
+        usize::try_from(self.pointer)
+            .ok()
+            .filter(|pc| *pc < self.code.len())
+    }
+
+    /// Number of instructions executed so far
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Makes [`Machine::run`] give up after `limit` executed instructions in total
+    pub fn set_step_limit(&mut self, limit: usize) {
+        self.step_limit = Some(limit);
+    }
+
+    /// Makes [`Machine::run`] stop before executing the instruction at `pc`
+    pub fn add_breakpoint(&mut self, pc: usize) {
+        self.breakpoints.insert(pc);
+    }
+
+    pub fn remove_breakpoint(&mut self, pc: usize) {
+        self.breakpoints.remove(&pc);
+    }
+
+    /// Starts recording every step executed by [`Machine::run`] or [`Machine::step`]
+    pub fn record_trace(&mut self) {
+        self.trace.get_or_insert_with(Vec::new);
+    }
+
+    /// Steps recorded since [`Machine::record_trace`] was called
+    pub fn trace(&self) -> &[TraceStep] {
+        self.trace.as_deref().unwrap_or_default()
+    }
+
+    /// Executes the next instruction, returning what it did or `None` if the program has halted
+    pub fn step(&mut self) -> Option<TraceStep> {
+        let (pc, change) = self.advance()?;
+        let step = TraceStep::new(pc, &self.code[pc], change);
+        if let Some(trace) = &mut self.trace {
+            trace.push(step.clone());
+        }
+        Some(step)
+    }
+
+    /// Executes the next instruction without allocating, returning its index and the value of
+    /// the register it wrote as `(before, after)`
+    fn advance(&mut self) -> Option<(usize, Option<(i64, i64)>)> {
+        let pc = self.pc()?;
+        let (offset, change) = execute(&self.code[pc], &mut self.registers);
+        self.pointer += offset as isize;
+        self.steps += 1;
+        self.paused_at = None;
+        Some((pc, change))
+    }
+
+    /// Runs until the program halts, a breakpoint is hit or the step limit is reached. A
+    /// breakpoint that just stopped the machine is skipped, so calling `run` again continues
+    /// after it.
+    pub fn run(&mut self) -> Stop {
+        loop {
+            match self.pc() {
+                None => return Stop::Halted,
+                Some(pc) if self.paused_at != Some(pc) && self.breakpoints.contains(&pc) => {
+                    self.paused_at = Some(pc);
+                    return Stop::Breakpoint(pc);
                 }
-                pointer += 1;
+                _ => {}
             }
-            // `inc r` increments register `r`, adding `1` to it,
-            // then continues with the next instruction.
-            Instruction::Inc(r) => {
-                if let Some(r) = registers.get_mut(r) {
-                    *r += 1;
-                }
-                pointer += 1;
+            if self.step_limit.is_some_and(|limit| self.steps >= limit) {
+                return Stop::StepLimit;
             }
-            // `jmp offset` is a jump; it continues with the instruction `offset` away relative to itself.
-            Instruction::Jmp(offset) => {
-                pointer += *offset as isize;
+            let (pc, change) = self.advance().unwrap();
+            if let Some(trace) = &mut self.trace {
+                trace.push(TraceStep::new(pc, &self.code[pc], change));
             }
-            // `jie r, offset` is like `jmp`, but only jumps if register `r` is even ("jump if even").
-            Instruction::Jie(r, offset) => {
-                if let Some(r) = registers.get_mut(r) {
-                    if *r % 2 == 0 {
-                        pointer += *offset as isize;
-                    } else {
-                        pointer += 1;
-                    }
+        }
+    }
+}
+
+/// Executes a single instruction, returning the offset to the next one and the value of the
+/// register it wrote as `(before, after)`
+fn execute(
+    instruction: &Instruction,
+    registers: &mut HashMap<String, i64>,
+) -> (i64, Option<(i64, i64)>) {
+    let mut update = |name: &str, op: fn(i64) -> i64| {
+        let register = match registers.get_mut(name) {
+            Some(register) => register,
+            None => registers.entry(name.to_owned()).or_insert(0),
+        };
+        let before = *register;
+        *register = op(before);
+        Some((before, *register))
+    };
+    match instruction {
+        // `hlf r` sets register `r` to half its current value,
+        // then continues with the next instruction.
+        Instruction::Hlf(r) => (1, update(r, |value| value / 2)),
+        // `tpl r` sets register `r` to triple its current value,
+        // then continues with the next instruction.
+        Instruction::Tpl(r) => (1, update(r, |value| value * 3)),
+        // `inc r` increments register `r`, adding `1` to it,
+        // then continues with the next instruction.
+        Instruction::Inc(r) => (1, update(r, |value| value + 1)),
+        // `jmp offset` is a jump; it continues with the instruction `offset` away relative to itself.
+        Instruction::Jmp(offset) => (*offset, None),
+        // `jie r, offset` is like `jmp`, but only jumps if register `r` is even ("jump if even").
+        Instruction::Jie(r, offset) => match registers.get(r) {
+            Some(value) if value % 2 != 0 => (1, None),
+            _ => (*offset, None),
+        },
+        // `jio r, offset` is like `jmp`, but only jumps if register `r` is `1` ("jump if one", not odd).
+        Instruction::Jio(r, offset) => match registers.get(r) {
+            Some(1) => (*offset, None),
+            _ => (1, None),
+        },
+    }
+}
+
+/// Instruction of a program prepared by [`accelerate`]
+#[derive(PartialEq, Debug, Clone)]
+pub enum Op {
+    Plain(Instruction),
+    /// Start of the loop every puzzle input ends with, which counts the steps of the
+    /// [Collatz sequence](https://en.wikipedia.org/wiki/Collatz_conjecture) from `x` down to `1`:
+    ///
+    /// ```plain
+    /// jio x, +8
+    /// inc steps
+    /// jie x, +4
+    /// tpl x
+    /// inc x
+    /// jmp +2
+    /// hlf x
+    /// jmp -7
+    /// ```
+    Collatz {
+        x: String,
+        steps: String,
+    },
+}
+
+/// Replaces the first instruction of every Collatz loop with an [`Op::Collatz`] which runs the
+/// whole loop natively. All other instructions stay in place so jump offsets remain valid.
+pub fn accelerate(code: &[Instruction]) -> Vec<Op> {
+    (0..code.len())
+        .map(|pc| collatz_loop(&code[pc..]).unwrap_or_else(|| Op::Plain(code[pc].clone())))
+        .collect()
+}
+
+fn collatz_loop(code: &[Instruction]) -> Option<Op> {
+    use Instruction::*;
+    match code.get(..8)? {
+        [Jio(x, 8), Inc(steps), Jie(x1, 4), Tpl(x2), Inc(x3), Jmp(2), Hlf(x4), Jmp(-7)]
+            if [x1, x2, x3, x4].iter().all(|r| *r == x) && steps != x =>
+        {
+            Some(Op::Collatz {
+                x: x.clone(),
+                steps: steps.clone(),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Runs a program prepared by [`accelerate`], returning `None` if it reaches a Collatz loop which
+/// never terminates because `x` is not positive
+pub fn exec_accelerated(
+    code: &[Op],
+    mut registers: HashMap<String, i64>,
+) -> Option<HashMap<String, i64>> {
+    let mut pointer: isize = 0;
+    while let Some(op) = usize::try_from(pointer).ok().and_then(|pc| code.get(pc)) {
+        match op {
+            Op::Plain(instruction) => pointer += execute(instruction, &mut registers).0 as isize,
+            Op::Collatz { x, steps } => {
+                let mut value = registers.get(x).copied().unwrap_or(0);
+                if value < 1 {
+                    return None;
                 }
-            }
-            // `jio r, offset` is like `jmp`, but only jumps if register `r` is `1` ("jump if one", not odd).
-            Instruction::Jio(r, offset) => {
-                if let Some(r) = registers.get_mut(r) {
-                    if *r == 1 {
-                        pointer += *offset as isize;
+                let mut count = registers.get(steps).copied().unwrap_or(0);
+                while value != 1 {
+                    count += 1;
+                    value = if value % 2 == 0 {
+                        value / 2
                     } else {
-                        pointer += 1;
-                    }
+                        value * 3 + 1
+                    };
                 }
+                registers.insert(x.clone(), value);
+                registers.insert(steps.clone(), count);
+                pointer += 8;
             }
         }
     }
-
-    registers
+    Some(registers)
 }
 
 /// Part 2: what is the value in register b after the program is finished executing if
@@ -176,4 +551,139 @@ inc a";
         let registers = exec(&parse_input(EXAMPLE), 0);
         assert_eq!(2, registers["a"]);
     }
+
+    #[test]
+    fn assemble_labels() {
+        let code = assemble(
+            "; sets a to 2
+start:
+    inc a
+    jio a, skip ; skips the tpl
+    tpl a
+
+skip: inc a
+    jmp end
+end:",
+        )
+        .unwrap();
+        assert_eq!(disassemble(&code), format!("{}\njmp +1\n", EXAMPLE));
+        assert_eq!(parse_input(&disassemble(&code)), code);
+    }
+
+    #[test]
+    fn assemble_errors() {
+        let errors = assemble(
+            "inc c
+jie a
+  jmp 3
+foo a
+jio a, nowhere
+1x: inc a",
+        )
+        .unwrap_err();
+        assert_eq!(
+            errors
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<String>>(),
+            vec![
+                "1:5: unknown register 'c'",
+                "2:1: jie expects 2 argument(s), found 1",
+                "3:7: expected offset like +2 or a label, found '3'",
+                "4:1: unknown instruction 'foo'",
+                "5:8: unknown label 'nowhere'",
+                "6:1: invalid label '1x'",
+            ]
+        );
+    }
+
+    #[test]
+    fn accelerated_collatz() {
+        let code = parse_input(
+            "inc a
+tpl a
+jio a, +8
+inc b
+jie a, +4
+tpl a
+inc a
+jmp +2
+hlf a
+jmp -7
+inc b",
+        );
+        let accelerated = accelerate(&code);
+        assert_eq!(
+            accelerated
+                .iter()
+                .filter(|op| matches!(op, Op::Collatz { .. }))
+                .count(),
+            1
+        );
+        for a in (0..300).chain([1_000, 27_000, 77_031]) {
+            let registers = HashMap::from([("a".to_owned(), a), ("b".to_owned(), 0)]);
+            assert_eq!(
+                exec_accelerated(&accelerated, registers),
+                Some(exec(&code, a)),
+                "a = {}",
+                a
+            );
+        }
+        let registers = HashMap::from([("a".to_owned(), -1)]);
+        assert_eq!(exec_accelerated(&accelerated, registers), None);
+    }
+
+    #[test]
+    fn machine_trace() {
+        let code = parse_input(EXAMPLE);
+        let mut machine = Machine::new(&code);
+        machine.record_trace();
+        assert_eq!(Stop::Halted, machine.run());
+        assert_eq!(
+            machine
+                .trace()
+                .iter()
+                .map(|step| (step.pc, step.changes.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (0, vec![("a".to_owned(), 0, 1)]),
+                (1, vec![]),
+                (3, vec![("a".to_owned(), 1, 2)]),
+            ]
+        );
+        assert_eq!(machine.step(), None);
+
+        let mut machine = Machine::new(&code);
+        machine.record_trace();
+        let step = machine.step().unwrap();
+        machine.run();
+        assert_eq!(machine.trace().len(), 3);
+        assert_eq!(machine.trace()[0], step);
+    }
+
+    #[test]
+    fn machine_breakpoints_and_limit() {
+        let code = parse_input(EXAMPLE);
+        let mut machine = Machine::new(&code);
+        machine.set_register("a", 5);
+        machine.add_breakpoint(2);
+        assert_eq!(Stop::Breakpoint(2), machine.run());
+        assert_eq!((machine.register("a"), machine.steps()), (6, 2));
+        assert_eq!(Stop::Halted, machine.run());
+        assert_eq!(machine.register("a"), 19);
+
+        let code = parse_input("inc a\ninc a");
+        let mut machine = Machine::new(&code);
+        machine.add_breakpoint(0);
+        assert_eq!(Stop::Breakpoint(0), machine.run());
+        assert_eq!(machine.steps(), 0);
+        assert_eq!(Stop::Halted, machine.run());
+        assert_eq!(machine.register("a"), 2);
+
+        let code = parse_input("inc a\njmp +0");
+        let mut machine = Machine::new(&code);
+        machine.set_step_limit(100);
+        assert_eq!(Stop::StepLimit, machine.run());
+        assert_eq!((machine.pc(), machine.steps()), (Some(1), 100));
+    }
 }
@@ -45,120 +45,625 @@
 //! -   `turn on 0,0 through 0,0` would increase the total brightness by `1`.
 //! -   `toggle 0,0 through 999,999` would increase the total brightness by `2000000`.
 
-use std::ops::Add;
+use anyhow::bail;
+use itertools::Itertools;
+use nom::branch::alt;
+use nom::bytes::complete::tag;
+use nom::character::complete::{char, space1, u32 as number};
+use nom::combinator::value;
+use nom::sequence::separated_pair;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::str::FromStr;
 
 // Solution Source: https://www.reddit.com/r/adventofcode/comments/3vmltn/day_6_solutions/cxptu4a/
-// TODO: rewrite using nom?
 
 /// Part 1: After following the instructions, how many lights are lit?
 #[aoc(day6, part1)]
-fn part1(input: &str) -> usize {
-    count(input, turn_on1, turn_off1, toggle1)
+fn part1(input: &str) -> u128 {
+    count(input, Grid::<bool>::new(1000, 1000))
+}
+
+/// Part 1 on a [`SparseGrid`], to benchmark against
+#[aoc(day6, part1, Sparse)]
+fn part1_sparse(input: &str) -> u128 {
+    count(input, SparseGrid::<bool>::new())
 }
 
 /// Part 2: What is the total brightness of all lights combined after following Santa's instructions?
 #[aoc(day6, part2)]
-fn part2(input: &str) -> usize {
-    count(input, turn_on2, turn_off2, toggle2)
-}
-
-fn count<T>(input: &str, on: fn(&mut T), off: fn(&mut T), toggle: fn(&mut T)) -> usize
-where
-    Grid<T>: GridCount,
-    T: Default,
-    T: Copy,
-{
-    let grid = &mut Grid {
-        data: vec![Default::default(); 1_000_000],
-    };
-
-    for ref mut line in input.lines() {
-        let instr: fn(&mut T) = if eat(line, "turn on ") {
-            on
-        } else if eat(line, "turn off ") {
-            off
-        } else if eat(line, "toggle ") {
-            toggle
-        } else {
-            panic!("Invalid instruction: '{}'", line)
-        };
+fn part2(input: &str) -> u128 {
+    count(input, Grid::<u32>::new(1000, 1000))
+}
 
-        let coords = line
-            .split(',')
-            .flat_map(|s| s.split(" through "))
-            .map(|s| s.parse::<usize>().unwrap())
-            .collect::<Vec<_>>();
+/// Part 2 on a [`SparseGrid`], to benchmark against
+#[aoc(day6, part2, Sparse)]
+fn part2_sparse(input: &str) -> u128 {
+    count(input, SparseGrid::<u32>::new())
+}
 
-        grid.operation(coords[0], coords[1], coords[2], coords[3], instr);
+/// Follows the instructions on `grid`, returning its count of lights
+pub fn count<G: LightGrid>(input: &str, mut grid: G) -> u128 {
+    let program =
+        parse_program(input).unwrap_or_else(|errors| panic!("{}", errors.iter().join("\n")));
+    for Instruction { action, from, to } in program {
+        grid.operation(action, from, to);
     }
-
     grid.count()
 }
 
-struct Grid<T> {
+/// What an instruction does to the lights of a rectangle
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Action {
+    TurnOn,
+    TurnOff,
+    Toggle,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Action::TurnOn => "turn on",
+            Action::TurnOff => "turn off",
+            Action::Toggle => "toggle",
+        })
+    }
+}
+
+/// One line of the instructions, e.g. `toggle 0,0 through 999,0`, with `from` the corner
+/// closest to `0,0`
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Instruction {
+    pub action: Action,
+    pub from: Point,
+    pub to: Point,
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {},{} through {},{}",
+            self.action, self.from.0, self.from.1, self.to.0, self.to.1
+        )
+    }
+}
+
+impl FromStr for Instruction {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_instruction(s).map_err(|(column, message)| ParseError {
+            line: 1,
+            column: column + 1,
+            message,
+        })
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parses the instructions, one per line, returning the errors of all invalid lines
+pub fn parse_program(input: &str) -> Result<Vec<Instruction>, Vec<ParseError>> {
+    let mut program = vec![];
+    let mut errors = vec![];
+    for (idx, line) in input.lines().enumerate() {
+        match parse_instruction(line) {
+            Ok(instruction) => program.push(instruction),
+            Err((column, message)) => errors.push(ParseError {
+                line: idx + 1,
+                column: column + 1,
+                message,
+            }),
+        }
+    }
+    if errors.is_empty() {
+        Ok(program)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Parses a single instruction, or returns the offset into `line` where it went wrong
+fn parse_instruction(line: &str) -> Result<Instruction, (usize, String)> {
+    let offset = |rest: &str| line.len() - rest.len();
+    let (rest, action) =
+        action(line).map_err(|_| (0, "expected `turn on`, `turn off` or `toggle`".to_string()))?;
+    let (rest, _) =
+        space1::<_, ()>(rest).map_err(|_| (offset(rest), "expected ` `".to_string()))?;
+    let (rest, from) = point(rest).map_err(|_| (offset(rest), "expected `x,y`".to_string()))?;
+    let (rest, _) = tag::<_, _, ()>(" through ")(rest)
+        .map_err(|_| (offset(rest), "expected ` through `".to_string()))?;
+    let (rest, to) = point(rest).map_err(|_| (offset(rest), "expected `x,y`".to_string()))?;
+    if !rest.is_empty() {
+        return Err((offset(rest), format!("unexpected `{}`", rest)));
+    }
+    Ok(Instruction {
+        action,
+        from: (from.0.min(to.0), from.1.min(to.1)),
+        to: (from.0.max(to.0), from.1.max(to.1)),
+    })
+}
+
+fn action(i: &str) -> nom::IResult<&str, Action, ()> {
+    alt((
+        value(Action::TurnOn, tag("turn on")),
+        value(Action::TurnOff, tag("turn off")),
+        value(Action::Toggle, tag("toggle")),
+    ))(i)
+}
+
+fn point(i: &str) -> nom::IResult<&str, Point, ()> {
+    separated_pair(number, char(','), number)(i)
+}
+
+/// A light, either on or off (`bool`) or with a brightness (`u32`)
+pub trait Light: Copy + Default {
+    /// What any sequence of actions does to a light, so that they can be combined before
+    /// being applied
+    type Effect: Copy + PartialEq;
+    const UNCHANGED: Self::Effect;
+
+    fn effect(action: Action) -> Self::Effect;
+    /// The effect of `first` followed by `second`
+    fn then(first: Self::Effect, second: Self::Effect) -> Self::Effect;
+    fn apply(self, effect: Self::Effect) -> Self;
+    /// What the light adds to the count of a grid
+    fn value(self) -> usize;
+}
+
+/// Effect of actions on a light which is on or off
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Switch {
+    Keep,
+    Set(bool),
+    Flip,
+}
+
+impl Light for bool {
+    type Effect = Switch;
+    const UNCHANGED: Switch = Switch::Keep;
+
+    fn effect(action: Action) -> Switch {
+        match action {
+            Action::TurnOn => Switch::Set(true),
+            Action::TurnOff => Switch::Set(false),
+            Action::Toggle => Switch::Flip,
+        }
+    }
+
+    fn then(first: Switch, second: Switch) -> Switch {
+        match (first, second) {
+            (first, Switch::Keep) => first,
+            (_, Switch::Set(on)) => Switch::Set(on),
+            (Switch::Keep, Switch::Flip) => Switch::Flip,
+            (Switch::Set(on), Switch::Flip) => Switch::Set(!on),
+            (Switch::Flip, Switch::Flip) => Switch::Keep,
+        }
+    }
+
+    fn apply(self, effect: Switch) -> bool {
+        match effect {
+            Switch::Keep => self,
+            Switch::Set(on) => on,
+            Switch::Flip => !self,
+        }
+    }
+
+    fn value(self) -> usize {
+        self as usize
+    }
+}
+
+/// Effect of actions on a brightness `b`: `max(floor, b + add)`
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Dimmer {
+    floor: i64,
+    add: i64,
+}
+
+impl Light for u32 {
+    type Effect = Dimmer;
+    const UNCHANGED: Dimmer = Dimmer { floor: 0, add: 0 };
+
+    fn effect(action: Action) -> Dimmer {
+        match action {
+            // increase the brightness of those lights by `1`
+            Action::TurnOn => Dimmer { floor: 0, add: 1 },
+            // decrease the brightness of those lights by `1`, to a minimum of zero
+            Action::TurnOff => Dimmer { floor: 0, add: -1 },
+            // increase the brightness of those lights by `2`
+            Action::Toggle => Dimmer { floor: 0, add: 2 },
+        }
+    }
+
+    fn then(first: Dimmer, second: Dimmer) -> Dimmer {
+        Dimmer {
+            floor: second.floor.max(first.floor + second.add),
+            add: first.add + second.add,
+        }
+    }
+
+    fn apply(self, effect: Dimmer) -> u32 {
+        effect.floor.max(self as i64 + effect.add) as u32
+    }
+
+    fn value(self) -> usize {
+        self as usize
+    }
+}
+
+/// Coordinates of a light
+pub type Point = (u32, u32);
+
+/// A grid of lights which follows instructions
+pub trait LightGrid: GridCount {
+    /// Applies `action` to the rectangle with opposite corners `from` and `to`, inclusive
+    fn operation(&mut self, action: Action, from: Point, to: Point);
+}
+
+pub trait GridCount {
+    /// How many lights are lit, or their total brightness, which can take more than 64 bits on
+    /// a [`SparseGrid`]
+    fn count(&self) -> u128;
+}
+
+/// Every light of the grid in memory
+#[derive(Clone, Debug, PartialEq)]
+pub struct Grid<T> {
+    width: usize,
     data: Vec<T>,
 }
 
-impl<T> Grid<T> {
-    fn operation<F: Fn(&mut T)>(&mut self, x1: usize, y1: usize, x2: usize, y2: usize, f: F) {
-        for j in y1..(y2 + 1) {
-            for i in x1..(x2 + 1) {
-                f(&mut self.data[i + j * 1000]);
+impl<T: Light> Grid<T> {
+    pub fn new(width: usize, height: usize) -> Self {
+        Grid {
+            width,
+            data: vec![Default::default(); width * height],
+        }
+    }
+}
+
+impl<T: Light> LightGrid for Grid<T> {
+    fn operation(&mut self, action: Action, (x1, y1): Point, (x2, y2): Point) {
+        let effect = T::effect(action);
+        for j in y1 as usize..(y2 as usize + 1) {
+            for i in x1 as usize..(x2 as usize + 1) {
+                let light = &mut self.data[i + j * self.width];
+                *light = light.apply(effect);
             }
         }
     }
 }
 
-trait GridCount {
-    fn count(&self) -> usize;
+impl<T: Light> GridCount for Grid<T> {
+    fn count(&self) -> u128 {
+        self.data.iter().map(|light| light.value() as u128).sum()
+    }
+}
+
+impl<T: Light> Grid<T> {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.data.len() / self.width.max(1)
+    }
+
+    /// The light at `(x, y)`, or `None` outside the grid
+    pub fn get(&self, (x, y): Point) -> Option<T> {
+        let (x, y) = (x as usize, y as usize);
+        if x >= self.width || y >= self.height() {
+            return None;
+        }
+        Some(self.data[x + y * self.width])
+    }
+
+    /// Count of the lights in the rectangle with opposite corners `from` and `to`, inclusive:
+    /// how many are lit, or their total brightness. The parts of the rectangle outside the grid
+    /// count as dark.
+    pub fn count_in(&self, from: Point, to: Point) -> usize {
+        let (x1, x2) = (from.0.min(to.0) as usize, from.0.max(to.0) as usize);
+        let (y1, y2) = (from.1.min(to.1) as usize, from.1.max(to.1) as usize);
+        if x1 >= self.width || y1 >= self.height() {
+            return 0;
+        }
+        let (x2, y2) = (x2.min(self.width - 1), y2.min(self.height() - 1));
+        (y1..=y2)
+            .flat_map(|j| &self.data[x1 + j * self.width..=x2 + j * self.width])
+            .map(|light| light.value())
+            .sum()
+    }
 }
 
-impl GridCount for Grid<bool> {
-    fn count(&self) -> usize {
-        self.data.iter().filter(|&s| *s).count()
+impl<T: Light + PartialEq> Grid<T> {
+    /// Every light which differs from `other`, in reading order, as `(point, self, other)`
+    pub fn diff(&self, other: &Grid<T>) -> Vec<(Point, T, T)> {
+        assert_eq!(
+            (self.width, self.data.len()),
+            (other.width, other.data.len()),
+            "grids of different sizes"
+        );
+        self.data
+            .iter()
+            .zip(&other.data)
+            .enumerate()
+            .filter(|(_, (a, b))| a != b)
+            .map(|(idx, (a, b))| {
+                let point = ((idx % self.width) as u32, (idx / self.width) as u32);
+                (point, *a, *b)
+            })
+            .collect()
     }
 }
 
-impl GridCount for Grid<u32> {
-    fn count(&self) -> usize {
-        self.data.iter().fold(0u32, Add::add) as usize
+/// Follows a program on a [`Grid`] one instruction at a time, so that the lights can be
+/// inspected in between
+pub struct Replay<T> {
+    program: Vec<Instruction>,
+    grid: Grid<T>,
+    executed: usize,
+}
+
+impl<T: Light> Replay<T> {
+    /// Fails if an instruction reaches outside of the `width` x `height` grid
+    pub fn new(program: Vec<Instruction>, width: usize, height: usize) -> anyhow::Result<Self> {
+        for (idx, instruction) in program.iter().enumerate() {
+            if instruction.to.0 as usize >= width || instruction.to.1 as usize >= height {
+                bail!(
+                    "instruction {} `{}` is outside of the {}x{} grid",
+                    idx + 1,
+                    instruction,
+                    width,
+                    height
+                );
+            }
+        }
+        Ok(Replay {
+            program,
+            grid: Grid::new(width, height),
+            executed: 0,
+        })
+    }
+
+    pub fn grid(&self) -> &Grid<T> {
+        &self.grid
+    }
+
+    pub fn program(&self) -> &[Instruction] {
+        &self.program
+    }
+
+    /// How many instructions have been followed so far
+    pub fn executed(&self) -> usize {
+        self.executed
+    }
+
+    /// Follows the next instruction and returns it, or `None` at the end of the program
+    pub fn step(&mut self) -> Option<Instruction> {
+        let instruction = *self.program.get(self.executed)?;
+        self.grid
+            .operation(instruction.action, instruction.from, instruction.to);
+        self.executed += 1;
+        Some(instruction)
+    }
+
+    /// Brings the grid to its state after the first `count` instructions, starting over if
+    /// more than that have been followed already
+    pub fn run_to(&mut self, count: usize) -> &Grid<T> {
+        let count = count.min(self.program.len());
+        if count < self.executed {
+            self.grid = Grid::new(self.grid.width, self.grid.height());
+            self.executed = 0;
+        }
+        while self.executed < count {
+            self.step();
+        }
+        &self.grid
+    }
+
+    /// Follows the rest of the program
+    pub fn finish(&mut self) -> &Grid<T> {
+        self.run_to(self.program.len())
     }
 }
 
-fn turn_on1(b: &mut bool) {
-    *b = true
+/// Grid with coordinates up to `2^32 - 1` which only keeps the instructions, and counts the
+/// lights by sweeping over the columns between the `x` coordinates of all rectangles.
+///
+/// A segment tree over the instructions in order holds in each node what its instructions do to
+/// the rows between all `y` coordinates, as runs of rows with the same effect. A rectangle is
+/// switched on in its leaf at its first column and off after its last one, which only rebuilds
+/// the nodes above that leaf, and the root then has the effect of all instructions covering the
+/// column. A node has at most `2k + 1` runs for `k` rectangles in it, so each change takes time
+/// in the order of the rectangles active at that column, and counting in the order of
+/// `instructions * active rectangles` no matter how large the grid is.
+pub struct SparseGrid<T> {
+    instructions: Vec<(Action, Point, Point)>,
+    light: PhantomData<T>,
 }
-fn turn_off1(b: &mut bool) {
-    *b = false
+
+impl<T: Light> SparseGrid<T> {
+    pub fn new() -> Self {
+        SparseGrid {
+            instructions: vec![],
+            light: PhantomData,
+        }
+    }
 }
-fn toggle1(b: &mut bool) {
-    *b = !*b
+
+impl<T: Light> Default for SparseGrid<T> {
+    fn default() -> Self {
+        SparseGrid::new()
+    }
 }
 
-fn eat(s: &mut &str, expect: &str) -> bool {
-    if s.starts_with(expect) {
-        *s = &s[expect.len()..];
-        true
-    } else {
-        false
+impl<T: Light> LightGrid for SparseGrid<T> {
+    fn operation(&mut self, action: Action, from: Point, to: Point) {
+        self.instructions.push((action, from, to));
     }
 }
 
-fn turn_on2(i: &mut u32) {
-    *i += 1
+impl<T: Light> GridCount for SparseGrid<T> {
+    fn count(&self) -> u128 {
+        // edges of the rectangles, as the first coordinate inside and the first one after
+        let edges = |coordinate: fn(&Point) -> u32| {
+            let mut edges: Vec<u64> = self
+                .instructions
+                .iter()
+                .flat_map(|(_, from, to)| [coordinate(from) as u64, coordinate(to) as u64 + 1])
+                .collect();
+            edges.sort_unstable();
+            edges.dedup();
+            edges
+        };
+        let columns = edges(|(x, _)| *x);
+        let rows = edges(|(_, y)| *y);
+        let index = |edges: &[u64], coordinate: u32, after: bool| {
+            edges
+                .binary_search(&(coordinate as u64 + after as u64))
+                .unwrap()
+        };
+        // instructions starting and ending at each column, as indices into `rectangles`
+        let mut starts = vec![vec![]; columns.len()];
+        let mut ends = vec![vec![]; columns.len()];
+        let mut rectangles: Vec<(T::Effect, Range<usize>)> = vec![];
+        for (idx, (action, from, to)) in self.instructions.iter().enumerate() {
+            starts[index(&columns, from.0, false)].push(idx);
+            ends[index(&columns, to.0, true)].push(idx);
+            let rows = index(&rows, from.1, false)..index(&rows, to.1, true);
+            rectangles.push((T::effect(*action), rows));
+        }
+        let mut tree = RunTree::<T>::new(self.instructions.len());
+        let mut count = 0;
+        for (column, edges) in columns.windows(2).enumerate() {
+            for &idx in &starts[column] {
+                let (effect, rows) = &rectangles[idx];
+                tree.set(idx, runs::<T>(*effect, rows));
+            }
+            for &idx in &ends[column] {
+                tree.set(idx, vec![(0, T::UNCHANGED)]);
+            }
+            let lights: u128 = tree
+                .root()
+                .iter()
+                .zip(
+                    tree.root()
+                        .iter()
+                        .skip(1)
+                        .map(|(row, _)| *row)
+                        .chain([rows.len() - 1]),
+                )
+                .map(|(&(from, effect), to)| {
+                    T::default().apply(effect).value() as u128 * (rows[to] - rows[from]) as u128
+                })
+                .sum();
+            count += lights * (edges[1] - edges[0]) as u128;
+        }
+        count
+    }
 }
-fn turn_off2(i: &mut u32) {
-    if *i > 0 {
-        *i -= 1
+
+/// What a sequence of instructions does to the rows, as `(first row, effect)` for each run of
+/// rows with the same effect, starting at row `0`
+type Runs<E> = Vec<(usize, E)>;
+
+/// Runs of a rectangle with `effect` on `rows`
+fn runs<T: Light>(effect: T::Effect, rows: &Range<usize>) -> Runs<T::Effect> {
+    let mut runs = vec![(0, T::UNCHANGED)];
+    if rows.start < rows.end {
+        if rows.start == 0 {
+            runs.clear();
+        }
+        runs.push((rows.start, effect));
+        runs.push((rows.end, T::UNCHANGED));
     }
+    runs
 }
-fn toggle2(i: &mut u32) {
-    *i += 2
+
+/// Sets `runs` to the runs of `first` followed by `second`
+fn then<T: Light>(
+    first: &[(usize, T::Effect)],
+    second: &[(usize, T::Effect)],
+    runs: &mut Runs<T::Effect>,
+) {
+    runs.clear();
+    let (mut i, mut j) = (0, 0);
+    loop {
+        let row = first[i].0.max(second[j].0);
+        let effect = T::then(first[i].1, second[j].1);
+        if runs.last().is_none_or(|(_, last)| *last != effect) {
+            runs.push((row, effect));
+        }
+        match (first.get(i + 1), second.get(j + 1)) {
+            (None, None) => return,
+            (Some(a), Some(b)) if a.0 == b.0 => {
+                i += 1;
+                j += 1;
+            }
+            (Some(a), Some(b)) if a.0 < b.0 => i += 1,
+            (Some(_), None) => i += 1,
+            _ => j += 1,
+        }
+    }
+}
+
+/// Segment tree over the instructions in order, where each node has the [`Runs`] of the
+/// instructions below it.
+///
+/// Nodes are numbered from `1` for the root, with the children of node `n` at `2n` and `2n + 1`,
+/// and the leaves of the instructions starting at `leaves`.
+struct RunTree<T: Light> {
+    leaves: usize,
+    nodes: Vec<Runs<T::Effect>>,
+}
+
+impl<T: Light> RunTree<T> {
+    fn new(instructions: usize) -> Self {
+        let leaves = instructions.next_power_of_two();
+        RunTree {
+            leaves,
+            nodes: vec![vec![(0, T::UNCHANGED)]; 2 * leaves],
+        }
+    }
+
+    /// Replaces the runs of instruction `idx`, and rebuilds the nodes above it
+    fn set(&mut self, idx: usize, runs: Runs<T::Effect>) {
+        let mut node = idx + self.leaves;
+        self.nodes[node] = runs;
+        while node > 1 {
+            node /= 2;
+            let mut runs = std::mem::take(&mut self.nodes[node]);
+            then::<T>(&self.nodes[2 * node], &self.nodes[2 * node + 1], &mut runs);
+            self.nodes[node] = runs;
+        }
+    }
+
+    /// Runs of every instruction
+    fn root(&self) -> &Runs<T::Effect> {
+        &self.nodes[1]
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_util::Lcg;
 
     #[test]
     fn part1_examples() {
@@ -181,4 +686,173 @@ mod tests {
         // `toggle 0,0 through 999,999` would increase the total brightness by `2000000`.
         assert_eq!(part2("toggle 0,0 through 999,999"), 2_000_000);
     }
+
+    const PROGRAM: &str = "turn on 0,0 through 9,9
+toggle 2,3 through 7,12
+turn off 5,0 through 5,19
+toggle 0,0 through 19,0
+turn off 3,3 through 4,4
+turn on 4,4 through 4,4
+toggle 1,1 through 18,18
+turn off 0,0 through 0,19";
+
+    #[test]
+    fn sparse_agrees_with_dense() {
+        for line in PROGRAM.lines() {
+            assert_eq!(part1_sparse(line), count(line, Grid::<bool>::new(20, 20)));
+            assert_eq!(part2_sparse(line), count(line, Grid::<u32>::new(20, 20)));
+        }
+        assert_eq!(
+            part1_sparse(PROGRAM),
+            count(PROGRAM, Grid::<bool>::new(20, 20))
+        );
+        assert_eq!(
+            part2_sparse(PROGRAM),
+            count(PROGRAM, Grid::<u32>::new(20, 20))
+        );
+        assert_eq!(part1_sparse(""), 0);
+    }
+
+    #[test]
+    fn sparse_agrees_with_dense_on_random_programs() {
+        let mut lcg = Lcg::new(19);
+        let mut random = |below: u32| lcg.below(below as u64) as u32;
+        for _ in 0..20 {
+            let mut program = vec![];
+            for _ in 0..random(60) {
+                let action = ["turn on", "turn off", "toggle"][random(3) as usize];
+                let (x1, y1, x2, y2) = (random(30), random(30), random(30), random(30));
+                program.push(format!("{} {},{} through {},{}", action, x1, y1, x2, y2));
+            }
+            let program = program.join("\n");
+            assert_eq!(
+                part1_sparse(&program),
+                count(&program, Grid::<bool>::new(30, 30))
+            );
+            assert_eq!(
+                part2_sparse(&program),
+                count(&program, Grid::<u32>::new(30, 30))
+            );
+        }
+    }
+
+    #[test]
+    fn sparse_scales() {
+        let program = "turn on 0,0 through 2147483647,2147483647
+toggle 1,1 through 2147483647,2147483647
+turn off 0,0 through 0,0";
+        assert_eq!(part1_sparse(program), (1 << 31) + (1 << 31) - 2);
+        assert_eq!(part2_sparse(program), (3 << 62) - (1 << 33) + 1);
+
+        let program = "turn on 0,0 through 4294967295,4294967295
+toggle 0,0 through 4294967295,4294967295
+toggle 0,0 through 4294967295,4294967295";
+        assert_eq!(part1_sparse(program), 1 << 64);
+        assert_eq!(part2_sparse(program), 5 << 64);
+    }
+
+    #[test]
+    fn parse_instructions() {
+        let instruction: Instruction = "toggle 7,3 through 2,12".parse().unwrap();
+        assert_eq!(
+            instruction,
+            Instruction {
+                action: Action::Toggle,
+                from: (2, 3),
+                to: (7, 12),
+            }
+        );
+        assert_eq!(instruction.to_string(), "toggle 2,3 through 7,12");
+        let program = parse_program(PROGRAM).unwrap();
+        assert_eq!(program.len(), 8);
+        let text: Vec<_> = program.iter().map(|i| i.to_string()).collect();
+        assert_eq!(text.join("\n"), PROGRAM);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let errors = parse_program(
+            "turn on 0,0 through 9,9
+switch 1,1 through 2,2
+turn off 1;1 through 2,2
+toggle 0,0 to 3,3
+turn on 0,0 through 9,9 twice
+toggle 0,0 through 99999999999,1",
+        )
+        .unwrap_err();
+        let errors: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "2:1: expected `turn on`, `turn off` or `toggle`",
+                "3:10: expected `x,y`",
+                "4:11: expected ` through `",
+                "5:24: unexpected ` twice`",
+                "6:20: expected `x,y`",
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "1:1: expected `turn on`, `turn off` or `toggle`\n2:10: expected `x,y`"
+    )]
+    fn count_reports_every_error() {
+        count(
+            "switch 1,1 through 2,2\nturn off 1;1 through 2,2",
+            Grid::<bool>::new(3, 3),
+        );
+    }
+
+    #[test]
+    fn replay() {
+        let program = parse_program(PROGRAM).unwrap();
+        let mut replay = Replay::<bool>::new(program.clone(), 20, 20).unwrap();
+        assert_eq!(replay.step(), Some(program[0]));
+        assert_eq!(replay.grid().count(), 100);
+        assert_eq!(replay.grid().count_in((0, 0), (4, 4)), 25);
+        // rectangles crossing the right and bottom edges only count the lights inside
+        assert_eq!(replay.grid().count_in((5, 0), (25, 0)), 5);
+        assert_eq!(replay.grid().count_in((0, 9), (30, 30)), 10);
+        assert_eq!(replay.grid().count_in((20, 0), (25, 5)), 0);
+        replay.run_to(3);
+        assert_eq!(replay.executed(), 3);
+        // toggled 2..=7 x 3..=9 off and 2..=7 x 10..=12 on, then turned column 5 off
+        assert_eq!(replay.grid().count_in((0, 0), (9, 12)), 100 - 42 + 18 - 6);
+        assert_eq!(replay.grid().get((5, 0)), Some(false));
+        assert_eq!(replay.grid().get((4, 12)), Some(true));
+        assert_eq!(replay.grid().get((20, 0)), None);
+        assert_eq!(
+            replay.finish().count(),
+            count(PROGRAM, Grid::<bool>::new(20, 20))
+        );
+        assert_eq!(replay.step(), None);
+        replay.run_to(1);
+        assert_eq!(replay.executed(), 1);
+        assert_eq!(replay.grid().count(), 100);
+
+        let mut brightness = Replay::<u32>::new(program, 20, 20).unwrap();
+        brightness.run_to(2);
+        assert_eq!(brightness.grid().count_in((2, 3), (2, 3)), 3);
+        assert_eq!(brightness.grid().count_in((7, 12), (2, 10)), 36);
+
+        let outside = parse_program("turn on 0,0 through 20,3").unwrap();
+        assert_eq!(
+            Replay::<bool>::new(outside, 20, 20)
+                .err()
+                .unwrap()
+                .to_string(),
+            "instruction 1 `turn on 0,0 through 20,3` is outside of the 20x20 grid"
+        );
+    }
+
+    #[test]
+    fn diff() {
+        let program = parse_program(PROGRAM).unwrap();
+        let mut replay = Replay::<bool>::new(program, 20, 20).unwrap();
+        let before = replay.run_to(5).clone();
+        let after = replay.run_to(6);
+        assert_eq!(after.diff(&before), vec![((4, 4), true, false)]);
+        assert!(after.diff(after).is_empty());
+    }
 }
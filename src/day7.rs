@@ -70,13 +70,34 @@
 //! **What new signal is ultimately provided to wire a?**
 
 #[aoc_generator(day7)]
-fn parse_input(input: &str) -> HashMap<String, Gate> {
+pub fn parse_input(input: &str) -> HashMap<String, Gate> {
+    parse_booklet(input).unwrap()
+}
+
+/// Parses an instructions booklet, reporting malformed lines and wires driven more than once
+pub fn parse_booklet<S: Signal>(
+    input: &str,
+) -> Result<HashMap<String, Gate<S>>, Vec<CircuitError>> {
     let mut gates = HashMap::new();
-    for line in input.lines() {
-        let (_, (gate, key)) = parse_gate(line).unwrap();
-        gates.insert(key, gate);
+    let mut errors = vec![];
+    for (idx, line) in input.lines().enumerate() {
+        match parse_gate(line) {
+            Ok((_, (gate, key))) if !key.is_empty() && key.chars().all(char::is_alphabetic) => {
+                if gates.insert(key.clone(), gate).is_some() {
+                    errors.push(CircuitError::DrivenTwice(key));
+                }
+            }
+            _ => errors.push(CircuitError::Syntax {
+                line: idx + 1,
+                text: line.into(),
+            }),
+        }
+    }
+    if errors.is_empty() {
+        Ok(gates)
+    } else {
+        Err(errors)
     }
-    gates
 }
 
 /// Part 1: what signal is ultimately provided to wire `a`?
@@ -89,126 +110,693 @@ fn part1(input: &HashMap<String, Gate>) -> u16 {
 /// Part 2: What new signal is ultimately provided to wire a?
 #[aoc(day7, part2)]
 fn part2(input: &HashMap<String, Gate>) -> u16 {
-    let mut input = input.clone();
-    let a = eval_wire("a", &input, &mut HashMap::new());
-    input.insert("b".into(), Gate::Set(Expr::Value(a)));
-    eval_wire("a", &input, &mut HashMap::new())
+    let mut circuit = Circuit::new(input.clone()).unwrap();
+    let a = circuit.signals()["a"];
+    circuit.apply_overrides(&[("b", a)]);
+    circuit.signals()["a"]
 }
 
 use nom::bytes::complete::tag;
-use nom::character::complete::{alpha1, digit1};
-use std::collections::HashMap;
+use nom::character::complete::{alpha1, char, digit1};
+use nom::sequence::delimited;
+use petgraph::dot::Dot;
+use petgraph::graph::{Graph, NodeIndex};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::hash::Hash;
+use std::ops::{BitAnd, BitOr, BitXor, Not};
+use std::str::FromStr;
+
+/// Unsigned integer type carrying the signals of a circuit, `u16` in the puzzle
+pub trait Signal:
+    Copy
+    + Eq
+    + Hash
+    + fmt::Debug
+    + fmt::Display
+    + FromStr
+    + Into<u64>
+    + Not<Output = Self>
+    + BitAnd<Output = Self>
+    + BitOr<Output = Self>
+    + BitXor<Output = Self>
+{
+    const BITS: u32;
+
+    /// Shifts left, where shifting out all bits leaves `0`
+    fn shift_left(self, amount: Self) -> Self;
+    /// Shifts right, where shifting out all bits leaves `0`
+    fn shift_right(self, amount: Self) -> Self;
+    fn rotate_left_by(self, amount: Self) -> Self;
+    fn rotate_right_by(self, amount: Self) -> Self;
+    /// Adds, wrapping around on overflow
+    fn add_wrapping(self, other: Self) -> Self;
+
+    /// Signal as a shift or rotate amount
+    fn amount(self) -> u32 {
+        u32::try_from(self.into()).unwrap_or(u32::MAX)
+    }
+}
+
+macro_rules! impl_signal {
+    ($($t:ty),*) => {$(
+        impl Signal for $t {
+            const BITS: u32 = <$t>::BITS;
+
+            fn shift_left(self, amount: Self) -> Self {
+                self.checked_shl(amount.amount()).unwrap_or(0)
+            }
+            fn shift_right(self, amount: Self) -> Self {
+                self.checked_shr(amount.amount()).unwrap_or(0)
+            }
+            fn rotate_left_by(self, amount: Self) -> Self {
+                self.rotate_left(amount.amount() % Self::BITS)
+            }
+            fn rotate_right_by(self, amount: Self) -> Self {
+                self.rotate_right(amount.amount() % Self::BITS)
+            }
+            fn add_wrapping(self, other: Self) -> Self {
+                self.wrapping_add(other)
+            }
+        }
+    )*};
+}
+
+impl_signal!(u8, u16, u32, u64);
 
 #[derive(PartialEq, Debug, Clone)]
-enum Expr {
+pub enum Expr<S = u16> {
     Wire(String),
-    Value(u16),
+    Value(S),
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum Gate<S = u16> {
+    Set(Expr<S>),
+    BinaryNot(Expr<S>),
+    BinaryAnd(Expr<S>, Expr<S>),
+    BinaryOr(Expr<S>, Expr<S>),
+    LeftShift(Expr<S>, Expr<S>),
+    RightShift(Expr<S>, Expr<S>),
+    BinaryXor(Expr<S>, Expr<S>),
+    BinaryNand(Expr<S>, Expr<S>),
+    BinaryNor(Expr<S>, Expr<S>),
+    Add(Expr<S>, Expr<S>),
+    LeftRotate(Expr<S>, Expr<S>),
+    RightRotate(Expr<S>, Expr<S>),
+}
+
+impl<S: Signal> Gate<S> {
+    /// Builds the binary gate written as `op` in the booklet
+    fn binary(op: &str, left: Expr<S>, right: Expr<S>) -> Option<Self> {
+        Some(match op {
+            "AND" => Gate::BinaryAnd(left, right),
+            "OR" => Gate::BinaryOr(left, right),
+            "LSHIFT" => Gate::LeftShift(left, right),
+            "RSHIFT" => Gate::RightShift(left, right),
+            "XOR" => Gate::BinaryXor(left, right),
+            "NAND" => Gate::BinaryNand(left, right),
+            "NOR" => Gate::BinaryNor(left, right),
+            "ADD" => Gate::Add(left, right),
+            "LROTATE" => Gate::LeftRotate(left, right),
+            "RROTATE" => Gate::RightRotate(left, right),
+            _ => return None,
+        })
+    }
+
+    /// Same gate reading `map(expr)` instead of each of its expressions
+    fn map_operands(&self, mut map: impl FnMut(&Expr<S>) -> Expr<S>) -> Self {
+        match self {
+            Gate::Set(value) => Gate::Set(map(value)),
+            Gate::BinaryNot(value) => Gate::BinaryNot(map(value)),
+            Gate::BinaryAnd(left, right)
+            | Gate::BinaryOr(left, right)
+            | Gate::LeftShift(left, right)
+            | Gate::RightShift(left, right)
+            | Gate::BinaryXor(left, right)
+            | Gate::BinaryNand(left, right)
+            | Gate::BinaryNor(left, right)
+            | Gate::Add(left, right)
+            | Gate::LeftRotate(left, right)
+            | Gate::RightRotate(left, right) => {
+                Gate::binary(self.op(), map(left), map(right)).unwrap()
+            }
+        }
+    }
+
+    /// Wires read by this gate
+    fn inputs(&self) -> Vec<&str> {
+        self.operands()
+            .into_iter()
+            .filter_map(|expr| match expr {
+                Expr::Wire(name) => Some(name.as_str()),
+                Expr::Value(_) => None,
+            })
+            .collect()
+    }
+
+    /// Name of the operation as written in the booklet
+    fn op(&self) -> &'static str {
+        match self {
+            Gate::Set(_) => "SET",
+            Gate::BinaryNot(_) => "NOT",
+            Gate::BinaryAnd(_, _) => "AND",
+            Gate::BinaryOr(_, _) => "OR",
+            Gate::LeftShift(_, _) => "LSHIFT",
+            Gate::RightShift(_, _) => "RSHIFT",
+            Gate::BinaryXor(_, _) => "XOR",
+            Gate::BinaryNand(_, _) => "NAND",
+            Gate::BinaryNor(_, _) => "NOR",
+            Gate::Add(_, _) => "ADD",
+            Gate::LeftRotate(_, _) => "LROTATE",
+            Gate::RightRotate(_, _) => "RROTATE",
+        }
+    }
+
+    /// Expressions this gate reads, in booklet order
+    fn operands(&self) -> Vec<&Expr<S>> {
+        match self {
+            Gate::Set(value) | Gate::BinaryNot(value) => vec![value],
+            Gate::BinaryAnd(left, right)
+            | Gate::BinaryOr(left, right)
+            | Gate::LeftShift(left, right)
+            | Gate::RightShift(left, right)
+            | Gate::BinaryXor(left, right)
+            | Gate::BinaryNand(left, right)
+            | Gate::BinaryNor(left, right)
+            | Gate::Add(left, right)
+            | Gate::LeftRotate(left, right)
+            | Gate::RightRotate(left, right) => vec![left, right],
+        }
+    }
+
+    /// Output signal of this gate, given a way to resolve its inputs
+    fn eval(&self, mut resolve: impl FnMut(&Expr<S>) -> S) -> S {
+        match self {
+            Gate::Set(value) => resolve(value),
+            Gate::BinaryAnd(left, right) => resolve(left) & resolve(right),
+            Gate::BinaryOr(left, right) => resolve(left) | resolve(right),
+            Gate::BinaryNot(value) => !resolve(value),
+            Gate::LeftShift(value, amount) => resolve(value).shift_left(resolve(amount)),
+            Gate::RightShift(value, amount) => resolve(value).shift_right(resolve(amount)),
+            Gate::BinaryXor(left, right) => resolve(left) ^ resolve(right),
+            Gate::BinaryNand(left, right) => !(resolve(left) & resolve(right)),
+            Gate::BinaryNor(left, right) => !(resolve(left) | resolve(right)),
+            Gate::Add(left, right) => resolve(left).add_wrapping(resolve(right)),
+            Gate::LeftRotate(value, amount) => resolve(value).rotate_left_by(resolve(amount)),
+            Gate::RightRotate(value, amount) => resolve(value).rotate_right_by(resolve(amount)),
+        }
+    }
 }
 
-#[derive(Clone)]
-enum Gate {
-    Set(Expr),
-    BinaryNot(Expr),
-    BinaryAnd(Expr, Expr),
-    BinaryOr(Expr, Expr),
-    LeftShift(Expr, Expr),
-    RightShift(Expr, Expr),
+impl<S: Signal> fmt::Display for Expr<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Wire(name) => write!(f, "{}", name),
+            Expr::Value(value) => write!(f, "{}", value),
+        }
+    }
 }
 
-fn parse_expr(input: &str) -> nom::IResult<&str, Expr, ()> {
+/// Formats the left hand side of a booklet instruction, e.g. `x AND y`
+impl<S: Signal> fmt::Display for Gate<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.operands()[..] {
+            [value] if matches!(self, Gate::Set(_)) => write!(f, "{}", value),
+            [value] => write!(f, "{} {}", self.op(), value),
+            [left, right] => write!(f, "{} {} {}", left, self.op(), right),
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Problems which make a circuit impossible to evaluate
+#[derive(Debug, PartialEq)]
+pub enum CircuitError {
+    /// Line `line` (1-based) is not a valid instruction
+    Syntax { line: usize, text: String },
+    /// A wire gets its signal from more than one source
+    DrivenTwice(String),
+    /// A wire is read by the listed wires but never gets a signal
+    Undriven { wire: String, readers: Vec<String> },
+    /// Wires forming a loop, each one feeding the next and the last one being the first again
+    Loop(Vec<String>),
+    /// A constant shift amount which would shift out all bits of the signal
+    ShiftOutOfRange {
+        wire: String,
+        amount: u64,
+        bits: u32,
+    },
+}
+
+impl fmt::Display for CircuitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CircuitError::Syntax { line, text } => {
+                write!(f, "line {}: invalid instruction '{}'", line, text)
+            }
+            CircuitError::DrivenTwice(wire) => write!(f, "wire {} is driven more than once", wire),
+            CircuitError::Undriven { wire, readers } => {
+                write!(
+                    f,
+                    "wire {} is read by {} but never driven",
+                    wire,
+                    readers.join(", ")
+                )
+            }
+            CircuitError::Loop(wires) => write!(f, "combinational loop: {}", wires.join(" -> ")),
+            CircuitError::ShiftOutOfRange { wire, amount, bits } => {
+                write!(
+                    f,
+                    "wire {} shifts all {} bits out by shifting {}",
+                    wire, bits, amount
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for CircuitError {}
+
+/// Checks that every wire read is driven, that no wire feeds back into itself and that constant
+/// shift amounts stay within the signal width
+pub fn validate<S: Signal>(gates: &HashMap<String, Gate<S>>) -> Result<(), Vec<CircuitError>> {
+    let mut wires: Vec<&String> = gates.keys().collect();
+    wires.sort();
+
+    let mut undriven: HashMap<&str, Vec<String>> = HashMap::new();
+    let mut errors = vec![];
+    for wire in &wires {
+        let gate = &gates[*wire];
+        for input in gate.inputs() {
+            if !gates.contains_key(input) {
+                undriven.entry(input).or_default().push(wire.to_string());
+            }
+        }
+        if let Gate::LeftShift(_, Expr::Value(amount)) | Gate::RightShift(_, Expr::Value(amount)) =
+            gate
+        {
+            let amount: u64 = (*amount).into();
+            if amount >= S::BITS as u64 {
+                errors.push(CircuitError::ShiftOutOfRange {
+                    wire: wire.to_string(),
+                    amount,
+                    bits: S::BITS,
+                });
+            }
+        }
+    }
+    let mut undriven: Vec<(&str, Vec<String>)> = undriven.into_iter().collect();
+    undriven.sort();
+    errors.extend(
+        undriven
+            .into_iter()
+            .map(|(wire, readers)| CircuitError::Undriven {
+                wire: wire.into(),
+                readers,
+            }),
+    );
+    errors.extend(
+        find_loops(gates, &wires)
+            .into_iter()
+            .map(CircuitError::Loop),
+    );
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+enum Visit {
+    Active,
+    Done,
+}
+
+/// Depth first search from every wire towards its inputs, reporting each loop found on the way
+/// in signal order
+fn find_loops<S: Signal>(gates: &HashMap<String, Gate<S>>, wires: &[&String]) -> Vec<Vec<String>> {
+    let mut visits: HashMap<&str, Visit> = HashMap::new();
+    let mut loops = vec![];
+    for start in wires {
+        if visits.contains_key(start.as_str()) {
+            continue;
+        }
+        visits.insert(start, Visit::Active);
+        let mut path: Vec<(&str, Vec<&str>)> = vec![(start, gates[*start].inputs())];
+        while let Some((wire, inputs)) = path.last_mut() {
+            let wire = *wire;
+            match inputs.pop() {
+                Some(input) => match visits.get(input) {
+                    None if gates.contains_key(input) => {
+                        visits.insert(input, Visit::Active);
+                        path.push((input, gates[input].inputs()));
+                    }
+                    Some(Visit::Active) => {
+                        let from = path.iter().position(|(wire, _)| *wire == input).unwrap();
+                        let mut wires: Vec<String> = path[from..]
+                            .iter()
+                            .rev()
+                            .map(|(wire, _)| wire.to_string())
+                            .collect();
+                        wires.rotate_right(1);
+                        wires.push(input.to_string());
+                        loops.push(wires);
+                    }
+                    _ => {}
+                },
+                None => {
+                    visits.insert(wire, Visit::Done);
+                    path.pop();
+                }
+            }
+        }
+    }
+    loops
+}
+
+fn parse_expr<S: Signal>(input: &str) -> nom::IResult<&str, Expr<S>, ()> {
     match alpha1::<&str, ()>(input) {
         Ok((tail, alpha)) => Ok((tail, Expr::Wire(alpha.into()))),
         _ => {
             let (tail, digit) = digit1(input)?;
-            Ok((tail, Expr::Value(digit.parse().unwrap())))
+            let value = digit.parse().map_err(|_| nom::Err::Error(()))?;
+            Ok((tail, Expr::Value(value)))
         }
     }
 }
 
-fn parse_gate(line: &str) -> nom::IResult<&str, (Gate, String), ()> {
+fn parse_gate<S: Signal>(line: &str) -> nom::IResult<&str, (Gate<S>, String), ()> {
     let parse_assign = tag::<&str, &str, ()>(" -> ");
-    Ok(match tag::<&str, &str, ()>("NOT ")(line) {
-        Ok((tail, _)) => {
-            let (tail, rov) = parse_expr(tail)?;
-            let (tail, _) = parse_assign(tail)?;
-            (tail, (Gate::BinaryNot(rov), tail.into()))
+    if let Ok((tail, _)) = tag::<&str, &str, ()>("NOT ")(line) {
+        let (tail, value) = parse_expr(tail)?;
+        let (tail, _) = parse_assign(tail)?;
+        return Ok((tail, (Gate::BinaryNot(value), tail.into())));
+    }
+    let (tail, left) = parse_expr(line)?;
+    if let Ok((tail, _)) = parse_assign(tail) {
+        return Ok((tail, (Gate::Set(left), tail.into())));
+    }
+    let (tail, op) = delimited(char(' '), alpha1, char(' '))(tail)?;
+    let (tail, right) = parse_expr(tail)?;
+    let (tail, _) = parse_assign(tail)?;
+    let gate = Gate::binary(op, left, right).ok_or(nom::Err::Error(()))?;
+    Ok((tail, (gate, tail.into())))
+}
+
+fn eval_wire<S: Signal>(
+    wire: &str,
+    gates: &HashMap<String, Gate<S>>,
+    cache: &mut HashMap<String, S>,
+) -> S {
+    if cache.contains_key(wire) {
+        return cache[wire];
+    }
+    let result = gates[wire].eval(|expr| match expr {
+        Expr::Wire(name) => eval_wire(name, gates, cache),
+        Expr::Value(value) => *value,
+    });
+    cache.insert(wire.into(), result);
+    result
+}
+
+/// A whole circuit which evaluates the signal of every wire at once
+pub struct Circuit<S = u16> {
+    gates: HashMap<String, Gate<S>>,
+    order: Vec<String>,
+    overrides: HashMap<String, S>,
+}
+
+impl<S: Signal> Circuit<S> {
+    /// Builds a circuit from the gates of an instructions booklet (see [`parse_booklet`]),
+    /// rejecting it if it can't be evaluated (see [`validate`])
+    pub fn new(gates: HashMap<String, Gate<S>>) -> Result<Self, Vec<CircuitError>> {
+        validate(&gates)?;
+        let order = topological_order(&gates);
+        Ok(Circuit {
+            gates,
+            order,
+            overrides: HashMap::new(),
+        })
+    }
+
+    /// Evaluates every wire in topological order and returns the signal on each of them
+    pub fn signals(&self) -> HashMap<String, S> {
+        let mut signals = self.overrides.clone();
+        for wire in &self.order {
+            if self.overrides.contains_key(wire) {
+                continue;
+            }
+            let signal = self.gates[wire].eval(|expr| match expr {
+                Expr::Wire(name) => signals[name],
+                Expr::Value(value) => *value,
+            });
+            signals.insert(wire.clone(), signal);
         }
-        _ => {
-            let (tail, left) = parse_expr(line)?;
-
-            match parse_assign(tail) {
-                Ok((tail, _)) => (tail, (Gate::Set(left), tail.into())),
-                _ => match tag::<&str, &str, ()>(" AND ")(tail) {
-                    Ok((tail, _)) => {
-                        let (tail, right) = parse_expr(tail)?;
-                        let (tail, _) = parse_assign(tail)?;
-                        (tail, (Gate::BinaryAnd(left, right), tail.into()))
-                    }
-                    _ => match tag::<&str, &str, ()>(" OR ")(tail) {
-                        Ok((tail, _)) => {
-                            let (tail, right) = parse_expr(tail)?;
-                            let (tail, _) = parse_assign(tail)?;
-                            (tail, (Gate::BinaryOr(left, right), tail.into()))
-                        }
-                        _ => match tag::<&str, &str, ()>(" LSHIFT ")(tail) {
-                            Ok((tail, _)) => {
-                                let (tail, right) = parse_expr(tail)?;
-                                let (tail, _) = parse_assign(tail)?;
-                                (tail, (Gate::LeftShift(left, right), tail.into()))
-                            }
-                            _ => {
-                                let (tail, _) = tag::<&str, &str, ()>(" RSHIFT ")(tail)?;
-                                let (tail, right) = parse_expr(tail)?;
-                                let (tail, _) = parse_assign(tail)?;
-                                (tail, (Gate::RightShift(left, right), tail.into()))
-                            }
-                        },
-                    },
-                },
+        signals
+    }
+
+    /// Overrides the given wires with fixed signals and returns the wires whose signal changed
+    /// because of it, sorted by name
+    pub fn apply_overrides(&mut self, overrides: &[(&str, S)]) -> Vec<String> {
+        let before = self.signals();
+        for (wire, signal) in overrides {
+            self.overrides.insert(wire.to_string(), *signal);
+        }
+        let mut changed: Vec<String> = self
+            .signals()
+            .into_iter()
+            .filter(|(wire, signal)| before.get(wire) != Some(signal))
+            .map(|(wire, _)| wire)
+            .collect();
+        changed.sort();
+        changed
+    }
+
+    /// Removes all overrides so every wire is driven by its gate again
+    pub fn clear_overrides(&mut self) {
+        self.overrides.clear();
+    }
+}
+
+/// Orders wires so that every wire comes after all wires its gate reads from, leaving out wires
+/// which are part of or fed by a loop
+fn topological_order<S: Signal>(gates: &HashMap<String, Gate<S>>) -> Vec<String> {
+    let mut wires: Vec<&str> = gates.keys().map(String::as_str).collect();
+    wires.sort_unstable();
+
+    let mut pending: HashMap<&str, usize> = HashMap::new();
+    let mut readers: HashMap<&str, Vec<&str>> = HashMap::new();
+    for wire in &wires {
+        let inputs: Vec<&str> = gates[*wire]
+            .inputs()
+            .into_iter()
+            .filter(|input| gates.contains_key(*input))
+            .collect();
+        pending.insert(wire, inputs.len());
+        for input in inputs {
+            readers.entry(input).or_default().push(wire);
+        }
+    }
+    let mut ready: VecDeque<&str> = wires
+        .into_iter()
+        .filter(|wire| pending[wire] == 0)
+        .collect();
+    let mut order = Vec::with_capacity(gates.len());
+    while let Some(wire) = ready.pop_front() {
+        order.push(wire.to_string());
+        for reader in readers.get(wire).into_iter().flatten() {
+            let count = pending.get_mut(reader).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                ready.push_back(reader);
             }
         }
-    })
+    }
+    order
 }
 
-fn eval_wire(wire: &str, gates: &HashMap<String, Gate>, cache: &mut HashMap<String, u16>) -> u16 {
-    if cache.contains_key(wire) {
-        return cache[wire];
+/// Simplifies a circuit while keeping the signals on `outputs`: folds gates whose inputs are all
+/// constant, makes readers of `x -> y` copies read `x` directly and drops every wire which does
+/// not contribute to an output
+pub fn optimize<S: Signal>(
+    gates: &HashMap<String, Gate<S>>,
+    outputs: &[&str],
+) -> HashMap<String, Gate<S>> {
+    let mut optimized = gates.clone();
+    for wire in topological_order(gates) {
+        let gate = optimized[&wire].map_operands(|expr| match expr {
+            Expr::Wire(name) => match optimized.get(name) {
+                Some(Gate::Set(source)) => source.clone(),
+                _ => expr.clone(),
+            },
+            Expr::Value(_) => expr.clone(),
+        });
+        let gate = if gate.inputs().is_empty() {
+            Gate::Set(Expr::Value(gate.eval(|expr| match expr {
+                Expr::Value(value) => *value,
+                Expr::Wire(_) => unreachable!(),
+            })))
+        } else {
+            gate
+        };
+        optimized.insert(wire, gate);
     }
-    let mut resolve = |expr: &Expr| -> u16 {
-        match expr {
-            Expr::Wire(name) => eval_wire(name, gates, cache),
-            Expr::Value(value) => *value,
+
+    let mut reachable: HashSet<&str> = HashSet::new();
+    let mut todo: Vec<&str> = outputs.to_vec();
+    while let Some(wire) = todo.pop() {
+        if let Some(gate) = optimized.get(wire) {
+            if reachable.insert(wire) {
+                todo.extend(gate.inputs());
+            }
         }
+    }
+    let reachable: HashSet<String> = reachable.into_iter().map(String::from).collect();
+    optimized.retain(|wire, _| reachable.contains(wire));
+    optimized
+}
+
+/// Prints a circuit in the instructions booklet format, one gate per line in evaluation order
+pub fn to_booklet<S: Signal>(gates: &HashMap<String, Gate<S>>) -> String {
+    let mut order = topological_order(gates);
+    let ordered: HashSet<&String> = order.iter().collect();
+    let mut looped: Vec<String> = gates
+        .keys()
+        .filter(|wire| !ordered.contains(wire))
+        .cloned()
+        .collect();
+    looped.sort();
+    order.extend(looped);
+    order
+        .into_iter()
+        .map(|wire| format!("{} -> {}\n", gates[&wire], wire))
+        .collect()
+}
+
+/// Node of a circuit graph
+#[derive(PartialEq, Debug, Clone)]
+pub enum CircuitNode<S = u16> {
+    /// A gate, labelled with its operation
+    Gate(&'static str),
+    /// A constant signal fed into a gate
+    Constant(S),
+    /// A wire which is read but never driven
+    Input(String),
+    /// A wire which no gate reads, i.e. an output of the circuit
+    Output(String),
+}
+
+impl<S: Signal> fmt::Display for CircuitNode<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CircuitNode::Gate(op) => write!(f, "{}", op),
+            CircuitNode::Constant(value) => write!(f, "{}", value),
+            CircuitNode::Input(wire) | CircuitNode::Output(wire) => write!(f, "{}", wire),
+        }
+    }
+}
+
+/// Edge of a circuit graph: the wire carrying the signal (none for constants) and, if known,
+/// the signal itself
+#[derive(PartialEq, Debug, Clone)]
+pub struct CircuitEdge<S = u16> {
+    pub wire: Option<String>,
+    pub signal: Option<S>,
+}
+
+impl<S: Signal> fmt::Display for CircuitEdge<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.wire, self.signal) {
+            (Some(wire), Some(signal)) => write!(f, "{} = {}", wire, signal),
+            (Some(wire), None) => write!(f, "{}", wire),
+            (None, _) => Ok(()),
+        }
+    }
+}
+
+/// Builds a graph with one node per gate and one edge per wire connection, optionally annotated
+/// with the signals from [`Circuit::signals`]
+pub fn circuit_graph<S: Signal>(
+    gates: &HashMap<String, Gate<S>>,
+    signals: Option<&HashMap<String, S>>,
+) -> Graph<CircuitNode<S>, CircuitEdge<S>> {
+    let mut wires: Vec<&String> = gates.keys().collect();
+    wires.sort();
+
+    let mut graph = Graph::new();
+    let mut drivers: HashMap<&str, NodeIndex> = HashMap::new();
+    for wire in &wires {
+        drivers.insert(wire, graph.add_node(CircuitNode::Gate(gates[*wire].op())));
+    }
+    let edge = |wire: &str| CircuitEdge {
+        wire: Some(wire.into()),
+        signal: signals.and_then(|signals| signals.get(wire).copied()),
     };
-    let result = match &gates[wire] {
-        Gate::Set(value) => resolve(value),
-        Gate::BinaryAnd(left, right) => resolve(left) & resolve(right),
-        Gate::BinaryOr(left, right) => resolve(left) | resolve(right),
-        Gate::BinaryNot(value) => !resolve(value),
-        Gate::LeftShift(value, amount) => resolve(value) << resolve(amount),
-        Gate::RightShift(value, amount) => resolve(value) >> resolve(amount),
-    };
-    cache.insert(wire.into(), result);
-    result
+    let mut read = HashSet::new();
+    for wire in &wires {
+        let gate = drivers[wire.as_str()];
+        for operand in gates[*wire].operands() {
+            match operand {
+                Expr::Wire(name) => {
+                    let driver = match drivers.get(name.as_str()) {
+                        Some(driver) => *driver,
+                        None => {
+                            let input = graph.add_node(CircuitNode::Input(name.clone()));
+                            drivers.insert(name, input);
+                            input
+                        }
+                    };
+                    graph.add_edge(driver, gate, edge(name));
+                    read.insert(name.as_str());
+                }
+                Expr::Value(value) => {
+                    let constant = graph.add_node(CircuitNode::Constant(*value));
+                    let edge = CircuitEdge {
+                        wire: None,
+                        signal: Some(*value),
+                    };
+                    graph.add_edge(constant, gate, edge);
+                }
+            }
+        }
+    }
+    for wire in wires {
+        if !read.contains(&wire.as_str()) {
+            let output = graph.add_node(CircuitNode::Output(wire.clone()));
+            graph.add_edge(drivers[wire.as_str()], output, edge(wire));
+        }
+    }
+    graph
+}
+
+/// Renders the circuit as a [Graphviz](https://graphviz.org/) DOT digraph, optionally annotated
+/// with the signals from [`Circuit::signals`]
+pub fn to_dot<S: Signal>(
+    gates: &HashMap<String, Gate<S>>,
+    signals: Option<&HashMap<String, S>>,
+) -> String {
+    format!("{}", Dot::new(&circuit_graph(gates, signals)))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    #[test]
-    fn part1_example() {
-        // For example, here is a simple circuit:
-        let gates = parse_input(
-            "123 -> x
+    const EXAMPLE: &str = "123 -> x
 456 -> y
 x AND y -> d
 x OR y -> e
 x LSHIFT 2 -> f
 y RSHIFT 2 -> g
 NOT x -> h
-NOT y -> i",
-        );
+NOT y -> i";
+
+    #[test]
+    fn part1_example() {
+        // For example, here is a simple circuit:
+        let gates = parse_input(EXAMPLE);
         let mut cache = HashMap::new();
         // After it is run, these are the signals on the wires:
         assert_eq!(eval_wire("d", &gates, &mut cache), 72);
@@ -221,9 +809,184 @@ NOT y -> i",
         assert_eq!(eval_wire("y", &gates, &mut cache), 456);
     }
 
+    #[test]
+    fn circuit_signals() {
+        let circuit = Circuit::new(parse_input(EXAMPLE)).unwrap();
+        // After it is run, these are the signals on the wires:
+        let expected: HashMap<String, u16> = [
+            ("d", 72),
+            ("e", 507),
+            ("f", 492),
+            ("g", 114),
+            ("h", 65412),
+            ("i", 65079),
+            ("x", 123),
+            ("y", 456),
+        ]
+        .into_iter()
+        .map(|(wire, signal)| (wire.to_string(), signal))
+        .collect();
+        assert_eq!(circuit.signals(), expected);
+    }
+
+    #[test]
+    fn circuit_overrides() {
+        let mut circuit = Circuit::new(parse_input(EXAMPLE)).unwrap();
+        assert_eq!(circuit.apply_overrides(&[("y", 456)]), Vec::<String>::new());
+        assert_eq!(
+            circuit.apply_overrides(&[("y", 0)]),
+            vec!["d", "e", "g", "i", "y"]
+        );
+        assert_eq!(circuit.signals()["e"], 123);
+        circuit.clear_overrides();
+        assert_eq!(circuit.signals()["e"], 507);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            parse_booklet::<u16>("123 -> x\nx AND -> y\n70000 -> z\n1 -> x").unwrap_err(),
+            vec![
+                CircuitError::Syntax {
+                    line: 2,
+                    text: "x AND -> y".into()
+                },
+                CircuitError::Syntax {
+                    line: 3,
+                    text: "70000 -> z".into()
+                },
+                CircuitError::DrivenTwice("x".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_errors() {
+        let gates = parse_input("b -> a\nc AND q -> b\na -> c\nd LSHIFT 16 -> e\nq OR 1 -> d\n");
+        assert_eq!(
+            validate(&gates).unwrap_err(),
+            vec![
+                CircuitError::ShiftOutOfRange {
+                    wire: "e".into(),
+                    amount: 16,
+                    bits: 16
+                },
+                CircuitError::Undriven {
+                    wire: "q".into(),
+                    readers: vec!["b".into(), "d".into()]
+                },
+                CircuitError::Loop(vec!["a".into(), "c".into(), "b".into(), "a".into()]),
+            ]
+        );
+        assert_eq!(
+            CircuitError::Loop(vec!["a".into(), "c".into(), "b".into(), "a".into()]).to_string(),
+            "combinational loop: a -> c -> b -> a"
+        );
+        assert!(Circuit::new(gates).is_err());
+        assert!(validate(&parse_input(EXAMPLE)).is_ok());
+    }
+
+    #[test]
+    fn dot_export() {
+        let gates = parse_input(EXAMPLE);
+        let graph = circuit_graph(&gates, None);
+        // 8 gates, 2 constant inputs, 2 constant shift amounts and 6 outputs
+        assert_eq!(graph.node_count(), 18);
+        assert_eq!(graph.edge_count(), 18);
+
+        let signals = Circuit::new(gates.clone()).unwrap().signals();
+        let dot = to_dot(&gates, Some(&signals));
+        assert!(dot.starts_with("digraph {"));
+        assert!(dot.contains("[ label = \"LSHIFT\" ]"));
+        assert!(dot.contains("[ label = \"x = 123\" ]"));
+        assert!(dot.contains("[ label = \"h = 65412\" ]"));
+    }
+
+    #[test]
+    fn optimize_circuit() {
+        let gates = parse_input(
+            "in -> x
+x -> y
+1 OR 2 -> c
+c LSHIFT 2 -> d
+y AND d -> out
+NOT c -> unused",
+        );
+        let optimized = optimize(&gates, &["out"]);
+        assert_eq!(to_booklet(&optimized), "in AND 12 -> out\n");
+
+        let gates = parse_input(EXAMPLE);
+        let optimized = optimize(&gates, &["d", "h"]);
+        assert_eq!(to_booklet(&optimized), "72 -> d\n65412 -> h\n");
+    }
+
+    #[test]
+    fn booklet_round_trip() {
+        let gates = parse_input(EXAMPLE);
+        let booklet = to_booklet(&gates);
+        assert_eq!(
+            booklet,
+            "123 -> x
+456 -> y
+x LSHIFT 2 -> f
+NOT x -> h
+x AND y -> d
+x OR y -> e
+y RSHIFT 2 -> g
+NOT y -> i
+"
+        );
+        assert_eq!(parse_input(&booklet), gates);
+    }
+
+    #[test]
+    fn other_widths() {
+        // NOT 0 and overflowing additions depend on the signal width
+        let booklet = "0 -> x\nNOT x -> y\n128 ADD 128 -> z\n";
+        let signals = Circuit::new(parse_booklet::<u8>(booklet).unwrap())
+            .unwrap()
+            .signals();
+        assert_eq!((signals["y"], signals["z"]), (u8::MAX, 0));
+        let signals = Circuit::new(parse_booklet::<u64>(booklet).unwrap())
+            .unwrap()
+            .signals();
+        assert_eq!((signals["y"], signals["z"]), (u64::MAX, 256));
+        assert!(parse_booklet::<u8>("256 -> x").is_err());
+        let booklet = "x LSHIFT 12 -> y\n1 -> x";
+        assert!(validate(&parse_booklet::<u8>(booklet).unwrap()).is_err());
+        assert!(validate(&parse_booklet::<u32>(booklet).unwrap()).is_ok());
+    }
+
+    #[test]
+    fn extra_gates() {
+        let gates = parse_booklet::<u8>(
+            "12 -> x
+10 -> y
+x XOR y -> a
+x NAND y -> b
+x NOR y -> c
+200 ADD x -> d
+129 LROTATE 1 -> e
+x RROTATE 3 -> f",
+        )
+        .unwrap();
+        let signals = Circuit::new(gates.clone()).unwrap().signals();
+        assert_eq!(signals["a"], 0b0000_0110);
+        assert_eq!(signals["b"], 0b1111_0111);
+        assert_eq!(signals["c"], 0b1111_0001);
+        assert_eq!(signals["d"], 212);
+        assert_eq!(signals["e"], 0b0000_0011);
+        assert_eq!(signals["f"], 0b1000_0001);
+        assert_eq!(parse_booklet(&to_booklet(&gates)).unwrap(), gates);
+        assert!(parse_booklet::<u16>("x MUL y -> z").is_err());
+    }
+
     #[test]
     fn test_parse_expr() {
-        assert_eq!(parse_expr("foo!").unwrap(), ("!", Expr::Wire("foo".into())));
-        assert_eq!(parse_expr("123!").unwrap(), ("!", Expr::Value(123)));
+        assert_eq!(
+            parse_expr::<u16>("foo!").unwrap(),
+            ("!", Expr::Wire("foo".into()))
+        );
+        assert_eq!(parse_expr::<u16>("123!").unwrap(), ("!", Expr::Value(123)));
     }
 }
@@ -47,9 +47,10 @@
 use itertools::Itertools;
 use regex::Regex;
 use std::collections::HashMap;
+use std::fmt;
 
 #[aoc_generator(day9)]
-fn parse_input(input: &str) -> anyhow::Result<HashMap<String, HashMap<String, u64>>> {
+pub fn parse_input(input: &str) -> anyhow::Result<RoutingMap> {
     let mut routes = HashMap::new();
     // London to Dublin = 464
     let re = Regex::new(r"^(?P<source>\w+) to (?P<target>\w+) = (?P<distance>\d+)$")?;
@@ -79,45 +80,523 @@ fn parse_input(input: &str) -> anyhow::Result<HashMap<String, HashMap<String, u6
     Ok(routes)
 }
 
-type RoutingMap = HashMap<String, HashMap<String, u64>>;
+/// Distance from each city to every other city it has a route to
+pub type RoutingMap = HashMap<String, HashMap<String, u64>>;
 
 /// Part 1: What is the distance of the shortest route?
 #[aoc(day9, part1)]
 fn part1(routes: &RoutingMap) -> u64 {
-    *lengths(routes)
-        .iter()
-        .min()
+    held_karp(&Matrix::new(routes), Goal::Shortest)
         .expect("routes should not be empty")
+        .distance
+}
+
+/// Part 1 by trying every permutation, to benchmark against
+#[aoc(day9, part1, BruteForce)]
+fn part1_brute_force(routes: &RoutingMap) -> u64 {
+    brute_force(&Matrix::new(routes), Goal::Shortest)
+        .expect("routes should not be empty")
+        .distance
 }
 
 /// Part 2: What is the distance of the longest route?
 #[aoc(day9, part2)]
 fn part2(routes: &RoutingMap) -> u64 {
-    *lengths(routes)
-        .iter()
-        .max()
+    held_karp(&Matrix::new(routes), Goal::Longest)
+        .expect("routes should not be empty")
+        .distance
+}
+
+/// Part 2 by trying every permutation, to benchmark against
+#[aoc(day9, part2, BruteForce)]
+fn part2_brute_force(routes: &RoutingMap) -> u64 {
+    brute_force(&Matrix::new(routes), Goal::Longest)
         .expect("routes should not be empty")
+        .distance
+}
+
+/// Which route to look for
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Goal {
+    Shortest,
+    Longest,
+}
+
+impl Goal {
+    /// Whether a route of distance `a` is better than one of distance `b`
+    fn better(self, a: u64, b: u64) -> bool {
+        match self {
+            Goal::Shortest => a < b,
+            Goal::Longest => a > b,
+        }
+    }
+
+    /// Distance as a cost to minimise
+    fn cost(self, distance: u64) -> i64 {
+        match self {
+            Goal::Shortest => distance as i64,
+            Goal::Longest => -(distance as i64),
+        }
+    }
+}
+
+/// A [`RoutingMap`] with the cities numbered in alphabetical order, so that they can be kept
+/// in bitmasks
+pub struct Matrix {
+    cities: Vec<String>,
+    distances: Vec<Vec<Option<u64>>>,
+}
+
+impl Matrix {
+    pub fn new(routes: &RoutingMap) -> Self {
+        let cities: Vec<String> = routes.keys().cloned().sorted().collect();
+        let distances = cities
+            .iter()
+            .map(|source| {
+                cities
+                    .iter()
+                    .map(|target| routes[source].get(target).copied())
+                    .collect()
+            })
+            .collect();
+        Matrix { cities, distances }
+    }
+
+    pub fn cities(&self) -> &[String] {
+        &self.cities
+    }
+
+    pub fn len(&self) -> usize {
+        self.cities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cities.is_empty()
+    }
+
+    /// Distance between the cities with indices `a` and `b`, if there is a route between them
+    pub fn distance(&self, a: usize, b: usize) -> Option<u64> {
+        self.distances[a][b]
+    }
+
+    /// Distance along `path` of city indices, if there are routes between all its stops
+    pub fn length(&self, path: &[usize]) -> Option<u64> {
+        path.windows(2)
+            .map(|flight| self.distance(flight[0], flight[1]))
+            .sum()
+    }
+
+    fn route(&self, path: Vec<usize>) -> Route {
+        Route {
+            distance: self.length(&path).unwrap(),
+            cities: path.iter().map(|&idx| self.cities[idx].clone()).collect(),
+        }
+    }
+}
+
+/// The cities of a route in the order they are visited, and its total distance
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Route {
+    pub cities: Vec<String>,
+    pub distance: u64,
+}
+
+/// Finds the best route visiting every city exactly once by trying every permutation, which
+/// takes factorial time. Returns `None` if no route connects all cities.
+pub fn brute_force(matrix: &Matrix, goal: Goal) -> Option<Route> {
+    let mut best: Option<(u64, Vec<usize>)> = None;
+    for path in (0..matrix.len()).permutations(matrix.len()) {
+        if let Some(distance) = matrix.length(&path) {
+            if best.as_ref().is_none_or(|(b, _)| goal.better(distance, *b)) {
+                best = Some((distance, path));
+            }
+        }
+    }
+    best.map(|(_, path)| matrix.route(path))
+}
+
+/// Finds the best route visiting every city exactly once with the Held-Karp algorithm: the best
+/// path through each set of visited cities which ends in each of them is built from the best
+/// paths through the set without that last city.
+///
+/// Takes time in the order of `2^n n^2` and memory in the order of `2^n n`, so more than
+/// [`MAX_CITIES`] are refused.
+pub fn held_karp(matrix: &Matrix, goal: Goal) -> Result<Route, RouteError> {
+    held_karp_path(matrix, goal, None, None, false).map(|path| matrix.route(path))
+}
+
+/// The most cities [`held_karp`] takes: 20 cities already need about 190 MB of tables and
+/// 4e8 steps, and each city more doubles both
+pub const MAX_CITIES: usize = 20;
+
+/// [`held_karp`] for paths which may have to start at `start`, end at `end`, or return from the
+/// last city to the first if `closed`
+fn held_karp_path(
+    matrix: &Matrix,
+    goal: Goal,
+    start: Option<usize>,
+    end: Option<usize>,
+    closed: bool,
+) -> Result<Vec<usize>, RouteError> {
+    let n = matrix.len();
+    if n > MAX_CITIES {
+        return Err(RouteError::TooManyCities(n));
+    }
+    let no_route = || RouteError::NoRoute(matrix.missing());
+    if n == 0 {
+        return Err(no_route());
+    }
+    const NONE: u64 = u64::MAX;
+    // best distance and previous city of paths through the cities in `mask`, ending at `last`,
+    // at `mask * n + last`
+    let mut best = vec![NONE; n << n];
+    let mut previous = vec![0u8; n << n];
+    for city in (0..n).filter(|&city| start.is_none_or(|start| start == city)) {
+        best[(1 << city) * n + city] = 0;
+    }
+    for mask in 1usize..1 << n {
+        for last in 0..n {
+            let distance = best[mask * n + last];
+            if distance == NONE {
+                continue;
+            }
+            for next in 0..n {
+                if mask & 1 << next != 0 {
+                    continue;
+                }
+                if let Some(flight) = matrix.distance(last, next) {
+                    let state = (mask | 1 << next) * n + next;
+                    let distance = distance + flight;
+                    if best[state] == NONE || goal.better(distance, best[state]) {
+                        best[state] = distance;
+                        previous[state] = last as u8;
+                    }
+                }
+            }
+        }
+    }
+    let full = (1 << n) - 1;
+    // distance of the whole route ending at `last`
+    let total = |last: usize| {
+        let distance = best[full * n + last];
+        if distance == NONE || end.is_some_and(|end| end != last) {
+            None
+        } else if closed && n > 1 {
+            Some(distance + matrix.distance(last, start?)?)
+        } else {
+            Some(distance)
+        }
+    };
+    let (mut last, _) = (0..n)
+        .filter_map(|last| Some((last, total(last)?)))
+        .reduce(|a, b| if goal.better(b.1, a.1) { b } else { a })
+        .ok_or_else(no_route)?;
+    let mut path = vec![last];
+    let mut mask = full;
+    while mask != 1 << last {
+        let before = previous[mask * n + last] as usize;
+        mask ^= 1 << last;
+        last = before;
+        path.push(last);
+    }
+    path.reverse();
+    if closed && n > 1 {
+        path.push(path[0]);
+    }
+    Ok(path)
+}
+
+/// Why no route could be found
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum RouteError {
+    UnknownCity(String),
+    /// No route satisfies the query, with the pairs of cities which have no distance between
+    /// them
+    NoRoute(Vec<(String, String)>),
+    /// More cities than [`held_karp`] can take
+    TooManyCities(usize),
+}
+
+impl fmt::Display for RouteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RouteError::UnknownCity(city) => write!(f, "unknown city '{}'", city),
+            RouteError::NoRoute(missing) if missing.is_empty() => {
+                write!(f, "no route visits every city")
+            }
+            RouteError::NoRoute(missing) => write!(
+                f,
+                "no route visits every city, missing distances: {}",
+                missing
+                    .iter()
+                    .map(|(a, b)| format!("{} to {}", a, b))
+                    .join(", ")
+            ),
+            RouteError::TooManyCities(n) => write!(
+                f,
+                "{} cities are too many, at most {} are supported",
+                n, MAX_CITIES
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RouteError {}
+
+impl Matrix {
+    /// Index of `city`
+    pub fn index(&self, city: &str) -> Result<usize, RouteError> {
+        self.cities
+            .iter()
+            .position(|c| c == city)
+            .ok_or_else(|| RouteError::UnknownCity(city.to_string()))
+    }
+
+    /// Pairs of cities which have no distance between them
+    pub fn missing(&self) -> Vec<(String, String)> {
+        (0..self.len())
+            .tuple_combinations()
+            .filter(|&(a, b)| self.distance(a, b).is_none())
+            .map(|(a, b)| (self.cities[a].clone(), self.cities[b].clone()))
+            .collect()
+    }
+
+    /// Best route visiting every city exactly once, starting at `from` and ending at `to` if
+    /// they are given
+    pub fn path(
+        &self,
+        goal: Goal,
+        from: Option<&str>,
+        to: Option<&str>,
+    ) -> Result<Route, RouteError> {
+        let start = from.map(|city| self.index(city)).transpose()?;
+        let end = to.map(|city| self.index(city)).transpose()?;
+        self.solve(goal, start, end, false)
+    }
+
+    /// Best round trip visiting every city exactly once and returning to the first one, which
+    /// is `from` or else the first city in alphabetical order. The route lists the first city
+    /// again at the end, unless it is the only city.
+    pub fn tour(&self, goal: Goal, from: Option<&str>) -> Result<Route, RouteError> {
+        let start = from.map(|city| self.index(city)).transpose()?;
+        self.solve(goal, Some(start.unwrap_or(0)), None, true)
+    }
+
+    fn solve(
+        &self,
+        goal: Goal,
+        start: Option<usize>,
+        end: Option<usize>,
+        closed: bool,
+    ) -> Result<Route, RouteError> {
+        held_karp_path(self, goal, start, end, closed).map(|path| self.route(path))
+    }
+}
+
+/// Distances between all pairs of cities as costs for `goal`, for the heuristics which need
+/// every pair of cities to be connected
+fn costs(matrix: &Matrix, goal: Goal) -> Result<Vec<Vec<i64>>, RouteError> {
+    let missing = matrix.missing();
+    if matrix.is_empty() || !missing.is_empty() {
+        return Err(RouteError::NoRoute(missing));
+    }
+    Ok((0..matrix.len())
+        .map(|a| {
+            (0..matrix.len())
+                .map(|b| goal.cost(matrix.distance(a, b).unwrap_or(0)))
+                .collect()
+        })
+        .collect())
+}
+
+/// Change in cost of `path` when reversing `path[i..=j]`
+fn reversal_delta(costs: &[Vec<i64>], path: &[usize], i: usize, j: usize) -> i64 {
+    let mut delta = 0;
+    if i > 0 {
+        delta += costs[path[i - 1]][path[j]] - costs[path[i - 1]][path[i]];
+    }
+    if j + 1 < path.len() {
+        delta += costs[path[i]][path[j + 1]] - costs[path[j]][path[j + 1]];
+    }
+    delta
+}
+
+/// Greedy route from the closest (or farthest) unvisited city each time, trying every city as
+/// the start. Takes time in the order of `n^3`.
+pub fn nearest_neighbour(matrix: &Matrix, goal: Goal) -> Result<Route, RouteError> {
+    let costs = costs(matrix, goal)?;
+    Ok(matrix.route(nearest_neighbour_path(&costs)))
 }
 
-fn lengths(routes: &RoutingMap) -> Vec<u64> {
-    routes
-        .keys()
-        .permutations(routes.keys().len())
-        .map(|perm| {
-            let mut len = 0;
-            for flight in perm.windows(2) {
-                let source = flight[0];
-                let target = flight[1];
-                len += routes[source][target];
+fn nearest_neighbour_path(costs: &[Vec<i64>]) -> Vec<usize> {
+    let n = costs.len();
+    (0..n)
+        .map(|start| {
+            let mut visited = vec![false; n];
+            visited[start] = true;
+            let mut path = vec![start];
+            let mut cost = 0;
+            while path.len() < n {
+                let last = *path.last().unwrap();
+                let next = (0..n)
+                    .filter(|&city| !visited[city])
+                    .min_by_key(|&city| costs[last][city])
+                    .unwrap();
+                visited[next] = true;
+                cost += costs[last][next];
+                path.push(next);
             }
-            len
+            (cost, path)
         })
-        .collect()
+        .min_by_key(|(cost, _)| *cost)
+        .unwrap()
+        .1
+}
+
+/// Improves the [`nearest_neighbour`] route by reversing parts of it for as long as that
+/// makes it better, until it is a local optimum
+pub fn two_opt(matrix: &Matrix, goal: Goal) -> Result<Route, RouteError> {
+    let costs = costs(matrix, goal)?;
+    let mut path = nearest_neighbour_path(&costs);
+    two_opt_path(&costs, &mut path);
+    Ok(matrix.route(path))
+}
+
+fn two_opt_path(costs: &[Vec<i64>], path: &mut [usize]) {
+    let mut improved = true;
+    while improved {
+        improved = false;
+        for i in 0..path.len() {
+            for j in i + 1..path.len() {
+                if reversal_delta(costs, path, i, j) < 0 {
+                    path[i..=j].reverse();
+                    improved = true;
+                }
+            }
+        }
+    }
+}
+
+/// Settings of [`anneal`]
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Annealing {
+    /// Seed of the random number generator, so that runs can be repeated
+    pub seed: u64,
+    /// How many random reversals to try
+    pub iterations: usize,
+    /// How much worse a reversal can make the route at the start and still be likely to be
+    /// kept, in units of distance
+    pub temperature: f64,
+    /// Factor by which the temperature drops after each iteration
+    pub cooling: f64,
+}
+
+impl Default for Annealing {
+    fn default() -> Self {
+        Annealing {
+            seed: 2015,
+            iterations: 200_000,
+            temperature: 100.0,
+            cooling: 0.99995,
+        }
+    }
+}
+
+/// Simulated annealing from the [`nearest_neighbour`] route: reverses a random part of the
+/// route, keeping the change if it makes the route better and otherwise with a probability of
+/// `e^(-worse / temperature)`, while the temperature drops. The best route seen is polished with
+/// [`two_opt`].
+pub fn anneal(matrix: &Matrix, goal: Goal, settings: &Annealing) -> Result<Route, RouteError> {
+    let costs = costs(matrix, goal)?;
+    let n = costs.len();
+    let mut path = nearest_neighbour_path(&costs);
+    let mut best = path.clone();
+    if n > 2 {
+        let mut random = Lcg(settings.seed);
+        let mut temperature = settings.temperature;
+        // cost of `path` relative to `best`
+        let mut cost = 0;
+        for _ in 0..settings.iterations {
+            let i = random.below(n as u64) as usize;
+            let j = random.below(n as u64 - 1) as usize;
+            let (i, j) = if j >= i { (i, j + 1) } else { (j, i) };
+            let delta = reversal_delta(&costs, &path, i, j);
+            if delta <= 0 || random.unit() < (-delta as f64 / temperature).exp() {
+                path[i..=j].reverse();
+                cost += delta;
+                if cost < 0 {
+                    best.clone_from(&path);
+                    cost = 0;
+                }
+            }
+            temperature *= settings.cooling;
+        }
+    }
+    two_opt_path(&costs, &mut best);
+    Ok(matrix.route(best))
+}
+
+/// Linear congruential generator
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.0 >> 33
+    }
+
+    /// Random number in `0..n`
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    /// Random number in `0.0..1.0`
+    fn unit(&mut self) -> f64 {
+        self.next() as f64 / (1u64 << 31) as f64
+    }
+}
+
+/// Route of a heuristic against the exact optimum
+#[derive(PartialEq, Debug, Clone)]
+pub struct Comparison {
+    pub solver: &'static str,
+    pub route: Route,
+    pub optimum: u64,
+    /// How much worse than the optimum the route is, as a fraction of the optimum
+    pub gap: f64,
+}
+
+/// Runs every heuristic and compares their routes with the one of [`held_karp`], which limits
+/// this to [`MAX_CITIES`] cities
+pub fn compare(
+    matrix: &Matrix,
+    goal: Goal,
+    settings: &Annealing,
+) -> Result<Vec<Comparison>, RouteError> {
+    let optimum = held_karp(matrix, goal)?.distance;
+    let routes = [
+        ("nearest neighbour", nearest_neighbour(matrix, goal)?),
+        ("2-opt", two_opt(matrix, goal)?),
+        ("simulated annealing", anneal(matrix, goal, settings)?),
+    ];
+    Ok(routes
+        .into_iter()
+        .map(|(solver, route)| Comparison {
+            solver,
+            gap: (goal.cost(route.distance) - goal.cost(optimum)) as f64 / optimum.max(1) as f64,
+            route,
+            optimum,
+        })
+        .collect())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_util;
 
     const EXAMPLE: &str = "London to Dublin = 464
 London to Belfast = 518
@@ -136,4 +615,265 @@ Dublin to Belfast = 141";
         // via (for example) `Dublin -> London -> Belfast`.
         assert_eq!(part2(&parse_input(EXAMPLE).unwrap()), 982);
     }
+
+    /// Every pair of `n` cities connected, with pseudo-random distances
+    fn random_map(n: usize, seed: u64) -> RoutingMap {
+        let mut random = test_util::Lcg::new(seed);
+        let mut input = vec![];
+        for a in 0..n {
+            for b in a + 1..n {
+                input.push(format!("City{} to City{} = {}", a, b, random.below(1000)));
+            }
+        }
+        parse_input(&input.join("\n")).unwrap()
+    }
+
+    #[test]
+    fn held_karp_agrees_with_brute_force() {
+        let matrix = Matrix::new(&parse_input(EXAMPLE).unwrap());
+        let shortest = held_karp(&matrix, Goal::Shortest).unwrap();
+        assert_eq!(
+            shortest.distance,
+            brute_force(&matrix, Goal::Shortest).unwrap().distance
+        );
+        // London -> Dublin -> Belfast = 605
+        assert_eq!(shortest.cities, vec!["London", "Dublin", "Belfast"]);
+        assert_eq!(held_karp(&matrix, Goal::Longest).unwrap().distance, 982);
+
+        for seed in 0..5 {
+            let matrix = Matrix::new(&random_map(7, seed));
+            for goal in [Goal::Shortest, Goal::Longest] {
+                let exact = held_karp(&matrix, goal).unwrap();
+                assert_eq!(exact.distance, brute_force(&matrix, goal).unwrap().distance);
+                let path: Vec<usize> = exact
+                    .cities
+                    .iter()
+                    .map(|city| matrix.cities().iter().position(|c| c == city).unwrap())
+                    .collect();
+                assert_eq!(
+                    path.iter().sorted().copied().collect::<Vec<_>>(),
+                    (0..7).collect::<Vec<_>>()
+                );
+                assert_eq!(matrix.length(&path), Some(exact.distance));
+            }
+        }
+    }
+
+    #[test]
+    fn held_karp_scales() {
+        let matrix = Matrix::new(&random_map(16, 42));
+        let shortest = held_karp(&matrix, Goal::Shortest).unwrap();
+        let longest = held_karp(&matrix, Goal::Longest).unwrap();
+        assert_eq!(shortest.cities.iter().unique().count(), 16);
+        assert_eq!(longest.cities.iter().unique().count(), 16);
+        assert!(shortest.distance < longest.distance);
+    }
+
+    #[test]
+    fn too_many_cities() {
+        let matrix = Matrix::new(&random_map(MAX_CITIES + 1, 42));
+        let error = Err(RouteError::TooManyCities(MAX_CITIES + 1));
+        assert_eq!(held_karp(&matrix, Goal::Shortest), error);
+        assert_eq!(matrix.path(Goal::Shortest, None, None), error);
+        assert_eq!(matrix.tour(Goal::Longest, None), error);
+        assert!(compare(&matrix, Goal::Shortest, &Annealing::default()).is_err());
+    }
+
+    #[test]
+    fn disconnected() {
+        let routes = parse_input(
+            "A to B = 1
+C to D = 1",
+        )
+        .unwrap();
+        assert_eq!(
+            held_karp(&Matrix::new(&routes), Goal::Shortest),
+            Err(RouteError::NoRoute(vec![
+                ("A".to_string(), "C".to_string()),
+                ("A".to_string(), "D".to_string()),
+                ("B".to_string(), "C".to_string()),
+                ("B".to_string(), "D".to_string()),
+            ]))
+        );
+        assert_eq!(brute_force(&Matrix::new(&routes), Goal::Shortest), None);
+    }
+
+    #[test]
+    fn route_queries() {
+        let matrix = Matrix::new(&parse_input(EXAMPLE).unwrap());
+        let path = |goal, from, to| {
+            let route = matrix.path(goal, from, to).unwrap();
+            (route.cities.join(" -> "), route.distance)
+        };
+        assert_eq!(
+            path(Goal::Shortest, None, None),
+            ("London -> Dublin -> Belfast".to_string(), 605)
+        );
+        assert_eq!(
+            path(Goal::Shortest, Some("Dublin"), None),
+            ("Dublin -> Belfast -> London".to_string(), 659)
+        );
+        assert_eq!(
+            path(Goal::Shortest, None, Some("London")),
+            ("Belfast -> Dublin -> London".to_string(), 605)
+        );
+        assert_eq!(
+            path(Goal::Longest, Some("London"), Some("Belfast")),
+            ("London -> Dublin -> Belfast".to_string(), 605)
+        );
+        let tour = matrix.tour(Goal::Shortest, Some("Dublin")).unwrap();
+        assert_eq!(tour.cities, vec!["Dublin", "London", "Belfast", "Dublin"]);
+        assert_eq!(tour.distance, 464 + 518 + 141);
+        let alone = Matrix::new(&RoutingMap::from([("Dublin".to_string(), HashMap::new())]));
+        let tour = alone.tour(Goal::Shortest, None).unwrap();
+        assert_eq!(
+            (tour.cities, tour.distance),
+            (vec!["Dublin".to_string()], 0)
+        );
+        assert_eq!(
+            matrix.path(Goal::Shortest, Some("Paris"), None),
+            Err(RouteError::UnknownCity("Paris".to_string()))
+        );
+        assert_eq!(
+            matrix.path(Goal::Shortest, Some("Dublin"), Some("Dublin")),
+            Err(RouteError::NoRoute(vec![]))
+        );
+    }
+
+    #[test]
+    fn route_queries_agree_with_brute_force() {
+        let matrix = Matrix::new(&random_map(6, 7));
+        let n = matrix.len();
+        for goal in [Goal::Shortest, Goal::Longest] {
+            let best = |paths: Vec<Vec<usize>>| {
+                let lengths = paths.iter().map(|path| matrix.length(path).unwrap());
+                match goal {
+                    Goal::Shortest => lengths.min().unwrap(),
+                    Goal::Longest => lengths.max().unwrap(),
+                }
+            };
+            for (from, to) in (0..n).cartesian_product(0..n).filter(|(a, b)| a != b) {
+                let expected = best(
+                    (0..n)
+                        .permutations(n)
+                        .filter(|path| path[0] == from && path[n - 1] == to)
+                        .collect(),
+                );
+                let (from, to) = (&matrix.cities()[from], &matrix.cities()[to]);
+                let route = matrix.path(goal, Some(from), Some(to)).unwrap();
+                assert_eq!(route.distance, expected);
+                assert_eq!((&route.cities[0], &route.cities[n - 1]), (from, to));
+            }
+            let expected = best(
+                (0..n)
+                    .permutations(n)
+                    .map(|mut path| {
+                        path.push(path[0]);
+                        path
+                    })
+                    .collect(),
+            );
+            for from in matrix.cities() {
+                let tour = matrix.tour(goal, Some(from)).unwrap();
+                assert_eq!(tour.distance, expected);
+                assert_eq!((&tour.cities[0], &tour.cities[n]), (from, from));
+            }
+        }
+    }
+
+    #[test]
+    fn disconnected_queries() {
+        let matrix = Matrix::new(
+            &parse_input(
+                "A to B = 1
+B to C = 2
+C to D = 3",
+            )
+            .unwrap(),
+        );
+        assert_eq!(matrix.path(Goal::Longest, None, None).unwrap().distance, 6);
+        assert_eq!(
+            matrix.path(Goal::Shortest, Some("B"), None),
+            Err(RouteError::NoRoute(vec![
+                ("A".to_string(), "C".to_string()),
+                ("A".to_string(), "D".to_string()),
+                ("B".to_string(), "D".to_string()),
+            ]))
+        );
+        assert_eq!(
+            matrix.tour(Goal::Shortest, None).unwrap_err().to_string(),
+            "no route visits every city, missing distances: A to C, A to D, B to D"
+        );
+    }
+
+    /// Whether `route` visits each of the `n` cities of `matrix` once and is as long as it says
+    fn check_route(matrix: &Matrix, route: &Route) {
+        let path: Vec<usize> = route
+            .cities
+            .iter()
+            .map(|city| matrix.index(city).unwrap())
+            .collect();
+        assert_eq!(path.iter().unique().count(), matrix.len());
+        assert_eq!(path.len(), matrix.len());
+        assert_eq!(matrix.length(&path), Some(route.distance));
+    }
+
+    #[test]
+    fn heuristics() {
+        for seed in 0..5 {
+            let matrix = Matrix::new(&random_map(9, seed));
+            let settings = Annealing {
+                seed,
+                iterations: 20_000,
+                ..Annealing::default()
+            };
+            for goal in [Goal::Shortest, Goal::Longest] {
+                let comparisons = compare(&matrix, goal, &settings).unwrap();
+                let names: Vec<_> = comparisons.iter().map(|c| c.solver).collect();
+                assert_eq!(names, ["nearest neighbour", "2-opt", "simulated annealing"]);
+                for comparison in &comparisons {
+                    check_route(&matrix, &comparison.route);
+                    assert!(comparison.gap >= 0.0);
+                }
+                assert!(comparisons[1].gap <= comparisons[0].gap);
+            }
+        }
+        let matrix = Matrix::new(&parse_input(EXAMPLE).unwrap());
+        for comparison in compare(&matrix, Goal::Shortest, &Annealing::default()).unwrap() {
+            assert_eq!(comparison.route.distance, 605);
+            assert_eq!(comparison.gap, 0.0);
+        }
+    }
+
+    #[test]
+    fn heuristics_scale() {
+        let matrix = Matrix::new(&random_map(150, 3));
+        let settings = Annealing {
+            iterations: 50_000,
+            ..Annealing::default()
+        };
+        let greedy = nearest_neighbour(&matrix, Goal::Shortest).unwrap();
+        let local = two_opt(&matrix, Goal::Shortest).unwrap();
+        let annealed = anneal(&matrix, Goal::Shortest, &settings).unwrap();
+        for route in [&greedy, &local, &annealed] {
+            check_route(&matrix, route);
+        }
+        assert!(local.distance <= greedy.distance);
+        assert_eq!(
+            annealed,
+            anneal(&matrix, Goal::Shortest, &settings).unwrap()
+        );
+    }
+
+    #[test]
+    fn heuristics_need_every_distance() {
+        let matrix = Matrix::new(&parse_input("A to B = 1\nB to C = 2").unwrap());
+        assert_eq!(
+            two_opt(&matrix, Goal::Shortest),
+            Err(RouteError::NoRoute(vec![(
+                "A".to_string(),
+                "C".to_string()
+            )]))
+        );
+    }
 }
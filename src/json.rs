@@ -0,0 +1,44 @@
+//! Required and optional fields of the JSON inputs some days accept
+
+use serde_json::Value;
+
+/// The string at `key` of `object`
+pub(crate) fn text(object: &Value, key: &str) -> anyhow::Result<String> {
+    object[key]
+        .as_str()
+        .map(String::from)
+        .ok_or_else(|| anyhow!("'{}' must be a string in {}", key, object))
+}
+
+/// The non-negative integer at `key` of `object`, or `default` if there is none. A missing field
+/// without a default is an error, as is a number which doesn't fit in `T`.
+pub(crate) fn number<T: TryFrom<u64>>(
+    object: &Value,
+    key: &str,
+    default: Option<T>,
+) -> anyhow::Result<T> {
+    match (&object[key], default) {
+        (Value::Null, Some(default)) => Ok(default),
+        (value, _) => value
+            .as_u64()
+            .and_then(|number| T::try_from(number).ok())
+            .ok_or_else(|| anyhow!("'{}' must be a non-negative integer in {}", key, object)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields() {
+        let object = json!({ "name": "Dagger", "cost": 8, "big": u64::MAX, "debt": -1 });
+        assert_eq!(text(&object, "name").unwrap(), "Dagger");
+        assert!(text(&object, "cost").is_err());
+        assert_eq!(number::<u64>(&object, "cost", None).unwrap(), 8);
+        assert_eq!(number::<i64>(&object, "armor", Some(0)).unwrap(), 0);
+        assert!(number::<u64>(&object, "armor", None).is_err());
+        assert!(number::<i64>(&object, "big", None).is_err());
+        assert!(number::<i64>(&object, "debt", Some(0)).is_err());
+    }
+}
@@ -0,0 +1,24 @@
+//! Helpers shared by the tests of several days, and by the benches through `benches/common.rs`
+
+/// Linear congruential generator for reproducible pseudo-random inputs
+pub struct Lcg(u64);
+
+impl Lcg {
+    pub fn new(seed: u64) -> Self {
+        Lcg(seed)
+    }
+
+    /// Random number in `0..2^31`
+    pub fn next_bits(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.0 >> 33
+    }
+
+    /// Random number in `0..n`
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_bits() % n
+    }
+}